@@ -2,6 +2,18 @@
 
 pub mod prelude;
 
+pub mod action_token;
+pub mod api_key;
+pub mod audit_log;
+pub mod auth_nonce;
 pub mod block_info;
+pub mod mfa_recovery_code;
+pub mod oauth_client;
+pub mod refresh_token;
+pub mod revoked_token;
 pub mod tx_info;
+pub mod user_identity;
 pub mod user_info;
+pub mod user_mfa;
+pub mod user_session;
+pub mod user_wallet;
@@ -1,5 +1,17 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.14
 
+pub use super::action_token::Entity as ActionToken;
+pub use super::api_key::Entity as ApiKey;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::auth_nonce::Entity as AuthNonce;
 pub use super::block_info::Entity as BlockInfo;
+pub use super::mfa_recovery_code::Entity as MfaRecoveryCode;
+pub use super::oauth_client::Entity as OauthClient;
+pub use super::refresh_token::Entity as RefreshToken;
+pub use super::revoked_token::Entity as RevokedToken;
 pub use super::tx_info::Entity as TxInfo;
+pub use super::user_identity::Entity as UserIdentity;
 pub use super::user_info::Entity as UserInfo;
+pub use super::user_mfa::Entity as UserMfa;
+pub use super::user_session::Entity as UserSession;
+pub use super::user_wallet::Entity as UserWallet;
@@ -20,6 +20,14 @@ pub struct Model {
     pub wallet_address: String,
     pub created_at: DateTimeUtc,
     pub updated_at: DateTimeUtc,
+    pub token_version: i32,
+    pub role: String,
+    pub failed_logins: i32,
+    pub locked_until: Option<DateTimeUtc>,
+    pub email_verified: bool,
+    pub deleted_at: Option<DateTimeUtc>,
+    pub purged_at: Option<DateTimeUtc>,
+    pub disabled_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
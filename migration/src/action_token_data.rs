@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ActionToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ActionToken::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ActionToken::Jti).string_len(64).not_null().unique_key())
+                    .col(ColumnDef::new(ActionToken::UserId).integer().not_null())
+                    .col(ColumnDef::new(ActionToken::Purpose).string_len(32).not_null())
+                    .col(ColumnDef::new(ActionToken::ExpiresAt).date_time().not_null())
+                    .col(ColumnDef::new(ActionToken::UsedAt).date_time().null())
+                    .col(ColumnDef::new(ActionToken::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_action_token_user_id")
+                    .table(ActionToken::Table)
+                    .col(ActionToken::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ActionToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ActionToken {
+    Table,
+    Id,
+    Jti,
+    UserId,
+    Purpose,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiKey::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ApiKey::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ApiKey::UserId).integer().not_null())
+                    .col(ColumnDef::new(ApiKey::Name).string_len(100).not_null())
+                    .col(ColumnDef::new(ApiKey::Prefix).string_len(16).not_null())
+                    .col(ColumnDef::new(ApiKey::KeyHash).string_len(64).not_null().unique_key())
+                    .col(ColumnDef::new(ApiKey::Scopes).text().not_null())
+                    .col(ColumnDef::new(ApiKey::ExpiresAt).date_time().null())
+                    .col(ColumnDef::new(ApiKey::LastUsedAt).date_time().null())
+                    .col(ColumnDef::new(ApiKey::RevokedAt).date_time().null())
+                    .col(ColumnDef::new(ApiKey::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_api_key_user_id")
+                    .table(ApiKey::Table)
+                    .col(ApiKey::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiKey::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ApiKey {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Prefix,
+    KeyHash,
+    Scopes,
+    ExpiresAt,
+    LastUsedAt,
+    RevokedAt,
+    CreatedAt,
+}
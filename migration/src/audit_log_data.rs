@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditLog::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditLog::Actor).string_len(80).not_null())
+                    .col(ColumnDef::new(AuditLog::Action).string_len(40).not_null())
+                    .col(ColumnDef::new(AuditLog::TargetUserId).integer().not_null())
+                    .col(ColumnDef::new(AuditLog::Details).text().not_null())
+                    .col(ColumnDef::new(AuditLog::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_log_target_user_id")
+                    .table(AuditLog::Table)
+                    .col(AuditLog::TargetUserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditLog::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuditLog {
+    Table,
+    Id,
+    Actor,
+    Action,
+    TargetUserId,
+    Details,
+    CreatedAt,
+}
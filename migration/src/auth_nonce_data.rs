@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthNonce::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuthNonce::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuthNonce::Nonce).string_len(64).not_null().unique_key())
+                    .col(ColumnDef::new(AuthNonce::ExpiresAt).date_time().not_null())
+                    .col(ColumnDef::new(AuthNonce::UsedAt).date_time().null())
+                    .col(ColumnDef::new(AuthNonce::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthNonce::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum AuthNonce {
+    Table,
+    Id,
+    Nonce,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
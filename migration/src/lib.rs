@@ -1,8 +1,26 @@
 pub use sea_orm_migration::prelude::*;
 
+mod action_token_data;
+mod audit_log_data;
+mod api_key_data;
+mod auth_nonce_data;
 mod block_data;
+mod oauth_client_data;
+mod refresh_token_data;
+mod revoked_token_data;
 mod tx_data;
 mod user_data;
+mod user_deletion_data;
+mod user_disabled_data;
+mod user_email_verified_data;
+mod user_identity_data;
+mod user_lockout_data;
+mod user_mfa_data;
+mod user_mfa_secret_data;
+mod user_session_data;
+mod user_role_data;
+mod user_token_version_data;
+mod user_wallet_data;
 
 pub struct Migrator;
 
@@ -13,6 +31,24 @@ impl MigratorTrait for Migrator {
             Box::new(user_data::Migration),
             Box::new(block_data::Migration),
             Box::new(tx_data::Migration),
+            Box::new(refresh_token_data::Migration),
+            Box::new(revoked_token_data::Migration),
+            Box::new(user_token_version_data::Migration),
+            Box::new(user_role_data::Migration),
+            Box::new(api_key_data::Migration),
+            Box::new(auth_nonce_data::Migration),
+            Box::new(user_mfa_data::Migration),
+            Box::new(user_lockout_data::Migration),
+            Box::new(user_email_verified_data::Migration),
+            Box::new(action_token_data::Migration),
+            Box::new(user_session_data::Migration),
+            Box::new(oauth_client_data::Migration),
+            Box::new(user_identity_data::Migration),
+            Box::new(user_wallet_data::Migration),
+            Box::new(user_deletion_data::Migration),
+            Box::new(user_disabled_data::Migration),
+            Box::new(audit_log_data::Migration),
+            Box::new(user_mfa_secret_data::Migration),
         ]
     }
 
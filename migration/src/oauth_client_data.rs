@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(OauthClient::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OauthClient::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(OauthClient::ClientId).string_len(64).not_null().unique_key())
+                    .col(ColumnDef::new(OauthClient::Name).string_len(100).not_null())
+                    .col(ColumnDef::new(OauthClient::SecretHash).string_len(64).not_null())
+                    .col(ColumnDef::new(OauthClient::Scopes).text().not_null())
+                    .col(ColumnDef::new(OauthClient::RevokedAt).date_time().null())
+                    .col(ColumnDef::new(OauthClient::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OauthClient::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum OauthClient {
+    Table,
+    Id,
+    ClientId,
+    Name,
+    SecretHash,
+    Scopes,
+    RevokedAt,
+    CreatedAt,
+}
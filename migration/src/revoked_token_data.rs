@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RevokedToken::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RevokedToken::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RevokedToken::Jti).string_len(36).not_null().unique_key())
+                    .col(ColumnDef::new(RevokedToken::UserId).integer().not_null())
+                    .col(ColumnDef::new(RevokedToken::ExpiresAt).date_time().not_null())
+                    .col(ColumnDef::new(RevokedToken::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RevokedToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum RevokedToken {
+    Table,
+    Id,
+    Jti,
+    UserId,
+    ExpiresAt,
+    CreatedAt,
+}
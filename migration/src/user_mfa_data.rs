@@ -0,0 +1,93 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserMfa::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserMfa::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserMfa::UserId).integer().not_null().unique_key())
+                    .col(ColumnDef::new(UserMfa::Secret).string_len(64).not_null())
+                    .col(ColumnDef::new(UserMfa::EnabledAt).date_time().null())
+                    .col(ColumnDef::new(UserMfa::LastUsedStep).big_integer().null())
+                    .col(ColumnDef::new(UserMfa::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(MfaRecoveryCode::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MfaRecoveryCode::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(MfaRecoveryCode::UserId).integer().not_null())
+                    .col(ColumnDef::new(MfaRecoveryCode::CodeHash).string_len(64).not_null())
+                    .col(ColumnDef::new(MfaRecoveryCode::UsedAt).date_time().null())
+                    .col(ColumnDef::new(MfaRecoveryCode::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_mfa_recovery_code_user_id")
+                    .table(MfaRecoveryCode::Table)
+                    .col(MfaRecoveryCode::UserId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MfaRecoveryCode::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(UserMfa::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum UserMfa {
+    Table,
+    Id,
+    UserId,
+    Secret,
+    EnabledAt,
+    LastUsedStep,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum MfaRecoveryCode {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    UsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // room for the encrypted secret, see `mfa::SecretCipher`
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserMfa::Table)
+                    .modify_column(ColumnDef::new(UserMfa::Secret).string_len(255).not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserMfa::Table)
+                    .modify_column(ColumnDef::new(UserMfa::Secret).string_len(64).not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum UserMfa {
+    Table,
+    Secret,
+}
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::DeriveIden;
+
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserWallet::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserWallet::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserWallet::UserId).integer().not_null())
+                    .col(ColumnDef::new(UserWallet::ChainId).big_integer().not_null())
+                    .col(ColumnDef::new(UserWallet::Address).string_len(42).not_null())
+                    .col(ColumnDef::new(UserWallet::Label).string_len(100).not_null())
+                    .col(ColumnDef::new(UserWallet::VerifiedAt).date_time().not_null())
+                    .col(ColumnDef::new(UserWallet::CreatedAt).date_time().not_null().default(Expr::current_timestamp()))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_wallet_user_id")
+                    .table(UserWallet::Table)
+                    .col(UserWallet::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // a wallet can only be linked to one account per chain
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_wallet_chain_address")
+                    .table(UserWallet::Table)
+                    .col(UserWallet::ChainId)
+                    .col(UserWallet::Address)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserWallet::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum UserWallet {
+    Table,
+    Id,
+    UserId,
+    ChainId,
+    Address,
+    Label,
+    VerifiedAt,
+    CreatedAt,
+}
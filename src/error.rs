@@ -1,6 +1,21 @@
+use actix_multipart::MultipartError;
+use actix_web::{
+    body::BoxBody,
+    error::{BlockingError, PayloadError},
+    http::{header, StatusCode},
+    HttpRequest, HttpResponse, ResponseError,
+};
 use derive_more::{Display, Error};
+use jsonwebtoken::errors::ErrorKind;
+use sea_orm::DbErr;
+use serde::Serialize;
+use uuid::Uuid;
 
+use crate::utils::trusted_issuers::TrustedIssuerError;
+use crate::utils::validation::FieldErrors;
 
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+pub const AUTH_REALM: &str = "rust_server";
 
 #[derive(Debug, Display, Error)]
 pub enum ServiceError {
@@ -16,13 +31,469 @@ pub enum ServiceError {
     #[display(fmt = "{error_message}")]
     Unauthorized { error_message: String },
 
+    /// An `Authorization` header with a scheme other than `Bearer`.
+    #[display(fmt = "{error_message}")]
+    InvalidAuthScheme { error_message: String },
+
+    /// Not a JWT at all: bad segments, base64 or JSON.
+    #[display(fmt = "{error_message}")]
+    MalformedToken { error_message: String },
+
+    /// A well-formed JWT we did not sign or cannot verify.
+    #[display(fmt = "{error_message}")]
+    InvalidToken { error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    TokenExpired { error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    TokenNotYetValid { error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    InvalidAudience { error_message: String },
+
     #[display(fmt = "{error_message}")]
     InternalServerError { error_message: String },
 
     #[display(fmt = "{error_message}")]
     BadRequest { error_message: String },
 
+    #[display(fmt = "{error_message}")]
+    Forbidden { error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    AccountDisabled { error_message: String },
+
     #[display(fmt = "{error_message}")]
     NotFound { error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    Conflict { error_message: String },
+
+    /// `etag` is the current version, so the client can retry without another read.
+    #[display(fmt = "{error_message}")]
+    PreconditionFailed {
+        error_message: String,
+        etag: Option<String>,
+    },
+
+    #[display(fmt = "{error_message}")]
+    PreconditionRequired { error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    PayloadTooLarge { error_message: String },
+
+    /// `accepted` lists the content types the endpoint takes instead.
+    #[display(fmt = "{error_message}")]
+    UnsupportedMediaType {
+        error_message: String,
+        accepted: Option<Accepted>,
+    },
+
+    #[display(fmt = "{error_message}")]
+    ValidationFailed {
+        error_message: String,
+        errors: FieldErrors,
+    },
+
+    #[display(fmt = "{error_message}")]
+    TooManyRequests {
+        error_message: String,
+        retry_after_seconds: i64,
+    },
+
+    #[display(fmt = "{error_message}")]
+    ServiceUnavailable { error_message: String },
 }
 
+/// Content types a 415 advertises, in the header for the rejected method.
+#[derive(Debug)]
+pub enum Accepted {
+    /// `Accept-Patch`, RFC 5789.
+    Patch(String),
+    /// `Accept-Post`, as registered by the W3C Linked Data Platform.
+    Post(String),
+}
+
+/// RFC 7807 problem details, plus our stable `code` and, for server side
+/// failures, the `correlation_id` under which the cause was logged.
+#[derive(Serialize, Debug)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: &'static str,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: String,
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<FieldErrors>,
+}
+
+impl ServiceError {
+    /// Stable, machine readable identifier of the error. Clients match on
+    /// these, so codes may be added but never renamed.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::DBConnectionError { .. } => "db_connection_failed",
+            ServiceError::BindAddressError { .. } => "bind_address_failed",
+            ServiceError::RunServerError { .. } => "run_server_failed",
+            ServiceError::Unauthorized { .. } => "unauthorized",
+            ServiceError::InvalidAuthScheme { .. } => "invalid_auth_scheme",
+            ServiceError::MalformedToken { .. } => "malformed_token",
+            ServiceError::InvalidToken { .. } => "invalid_token",
+            ServiceError::TokenExpired { .. } => "token_expired",
+            ServiceError::TokenNotYetValid { .. } => "token_not_yet_valid",
+            ServiceError::InvalidAudience { .. } => "invalid_audience",
+            ServiceError::InternalServerError { .. } => "internal_error",
+            ServiceError::BadRequest { .. } => "bad_request",
+            ServiceError::Forbidden { .. } => "forbidden",
+            ServiceError::AccountDisabled { .. } => "account_disabled",
+            ServiceError::NotFound { .. } => "not_found",
+            ServiceError::Conflict { .. } => "conflict",
+            ServiceError::PreconditionFailed { .. } => "precondition_failed",
+            ServiceError::PreconditionRequired { .. } => "precondition_required",
+            ServiceError::PayloadTooLarge { .. } => "payload_too_large",
+            ServiceError::UnsupportedMediaType { .. } => "unsupported_media_type",
+            ServiceError::ValidationFailed { .. } => "validation_failed",
+            ServiceError::TooManyRequests { .. } => "too_many_requests",
+            ServiceError::ServiceUnavailable { .. } => "service_unavailable",
+        }
+    }
+
+    /// Wraps any error whose message must not reach the client.
+    pub fn internal(err: impl std::fmt::Display) -> Self {
+        ServiceError::InternalServerError {
+            error_message: err.to_string(),
+        }
+    }
+
+    fn is_internal(&self) -> bool {
+        self.status_code().is_server_error()
+    }
+
+    /// RFC 6750 challenge. Every 401 needs one, and rejected tokens say why.
+    fn www_authenticate(&self) -> Option<String> {
+        match self {
+            ServiceError::MalformedToken { error_message }
+            | ServiceError::InvalidToken { error_message }
+            | ServiceError::TokenExpired { error_message }
+            | ServiceError::TokenNotYetValid { error_message }
+            | ServiceError::InvalidAudience { error_message } => Some(format!(
+                "Bearer realm=\"{}\", error=\"invalid_token\", error_description=\"{}\"",
+                AUTH_REALM,
+                error_message.replace(['"', '\\'], "")
+            )),
+            _ if self.status_code() == StatusCode::UNAUTHORIZED => {
+                Some(format!("Bearer realm=\"{}\"", AUTH_REALM))
+            }
+            _ => None,
+        }
+    }
+
+    /// The problem document for this error. Server side errors get a fresh
+    /// correlation id and only a generic detail; the real message is logged.
+    pub fn problem(&self) -> Problem {
+        let status = self.status_code();
+
+        let (detail, correlation_id) = if self.is_internal() {
+            let correlation_id = Uuid::new_v4().to_string();
+            log::error!("[{}] {}: {}", correlation_id, self.code(), self);
+
+            let detail = match self {
+                ServiceError::ServiceUnavailable { .. } => "The service is temporarily unavailable",
+                _ => "An internal error occurred",
+            };
+            (detail.to_owned(), Some(correlation_id))
+        } else {
+            (self.to_string(), None)
+        };
+
+        let errors = match self {
+            ServiceError::ValidationFailed { errors, .. } => Some(errors.clone()),
+            _ => None,
+        };
+
+        Problem {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            detail,
+            code: self.code(),
+            correlation_id,
+            errors,
+        }
+    }
+}
+
+impl ResponseError for ServiceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServiceError::Unauthorized { .. }
+            | ServiceError::InvalidAuthScheme { .. }
+            | ServiceError::MalformedToken { .. }
+            | ServiceError::InvalidToken { .. }
+            | ServiceError::TokenExpired { .. }
+            | ServiceError::TokenNotYetValid { .. }
+            | ServiceError::InvalidAudience { .. } => StatusCode::UNAUTHORIZED,
+            ServiceError::BadRequest { .. } => StatusCode::BAD_REQUEST,
+            ServiceError::Forbidden { .. } | ServiceError::AccountDisabled { .. } => {
+                StatusCode::FORBIDDEN
+            }
+            ServiceError::NotFound { .. } => StatusCode::NOT_FOUND,
+            ServiceError::Conflict { .. } => StatusCode::CONFLICT,
+            ServiceError::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
+            ServiceError::PreconditionRequired { .. } => StatusCode::PRECONDITION_REQUIRED,
+            ServiceError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ServiceError::UnsupportedMediaType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ServiceError::ValidationFailed { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ServiceError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ServiceError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::DBConnectionError { .. }
+            | ServiceError::BindAddressError { .. }
+            | ServiceError::RunServerError { .. }
+            | ServiceError::InternalServerError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        let problem = self.problem();
+
+        let mut builder = HttpResponse::build(self.status_code());
+        builder.insert_header((header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE));
+        if let Some(challenge) = self.www_authenticate() {
+            builder.insert_header((header::WWW_AUTHENTICATE, challenge));
+        }
+
+        match self {
+            ServiceError::PreconditionFailed {
+                etag: Some(etag), ..
+            } => {
+                builder.insert_header((header::ETAG, etag.as_str()));
+            }
+            ServiceError::UnsupportedMediaType {
+                accepted: Some(Accepted::Patch(accepted)),
+                ..
+            } => {
+                builder.insert_header(("Accept-Patch", accepted.as_str()));
+            }
+            ServiceError::UnsupportedMediaType {
+                accepted: Some(Accepted::Post(accepted)),
+                ..
+            } => {
+                builder.insert_header(("Accept-Post", accepted.as_str()));
+            }
+            ServiceError::TooManyRequests {
+                retry_after_seconds,
+                ..
+            } => {
+                builder.insert_header((header::RETRY_AFTER, (*retry_after_seconds).max(1).to_string()));
+            }
+            _ => {}
+        }
+
+        builder.body(serde_json::to_string(&problem).unwrap_or_default())
+    }
+}
+
+/// Error handler for the `web::Json`, `web::Query` and `web::Path` extractors,
+/// so malformed requests get a problem document like everything else.
+pub fn extractor_error<E: ResponseError>(err: E, _req: &HttpRequest) -> actix_web::Error {
+    let error_message = err.to_string();
+
+    match err.status_code() {
+        StatusCode::PAYLOAD_TOO_LARGE => ServiceError::PayloadTooLarge { error_message },
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => ServiceError::UnsupportedMediaType {
+            error_message,
+            accepted: None,
+        },
+        StatusCode::NOT_FOUND => ServiceError::NotFound { error_message },
+        _ => ServiceError::BadRequest { error_message },
+    }
+    .into()
+}
+
+impl From<DbErr> for ServiceError {
+    fn from(err: DbErr) -> Self {
+        ServiceError::internal(err)
+    }
+}
+
+impl From<BlockingError> for ServiceError {
+    fn from(err: BlockingError) -> Self {
+        ServiceError::internal(err)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ServiceError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        match err.kind() {
+            ErrorKind::InvalidToken | ErrorKind::Base64(_) | ErrorKind::Json(_) | ErrorKind::Utf8(_) => {
+                ServiceError::MalformedToken {
+                    error_message: "Token is malformed".to_owned(),
+                }
+            }
+            ErrorKind::ExpiredSignature => ServiceError::TokenExpired {
+                error_message: "Token has expired".to_owned(),
+            },
+            ErrorKind::ImmatureSignature => ServiceError::TokenNotYetValid {
+                error_message: "Token is not valid yet".to_owned(),
+            },
+            ErrorKind::InvalidAudience => ServiceError::InvalidAudience {
+                error_message: "Token is meant for another audience".to_owned(),
+            },
+            ErrorKind::InvalidSignature
+            | ErrorKind::InvalidAlgorithm
+            | ErrorKind::InvalidIssuer
+            | ErrorKind::InvalidSubject
+            | ErrorKind::MissingRequiredClaim(_) => ServiceError::InvalidToken {
+                error_message: "Token is invalid".to_owned(),
+            },
+            // key and crypto failures are ours, not the client's
+            _ => ServiceError::internal(err),
+        }
+    }
+}
+
+impl From<TrustedIssuerError> for ServiceError {
+    fn from(err: TrustedIssuerError) -> Self {
+        match err {
+            TrustedIssuerError::Token(err) => ServiceError::from(err),
+            TrustedIssuerError::Invalid(error_message) => ServiceError::InvalidToken { error_message },
+            TrustedIssuerError::UnknownUser => ServiceError::Unauthorized {
+                error_message: err.to_string(),
+            },
+            TrustedIssuerError::Jwks(error_message) => ServiceError::ServiceUnavailable { error_message },
+            TrustedIssuerError::Config(_) => ServiceError::internal(err),
+            TrustedIssuerError::Db(err) => ServiceError::internal(err),
+        }
+    }
+}
+
+impl From<MultipartError> for ServiceError {
+    fn from(err: MultipartError) -> Self {
+        match err {
+            MultipartError::Payload(PayloadError::Overflow) => ServiceError::PayloadTooLarge {
+                error_message: "File is too large".to_owned(),
+            },
+            err => ServiceError::BadRequest {
+                error_message: err.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    async fn body_of(err: ServiceError) -> serde_json::Value {
+        let response = err.error_response();
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+
+        serde_json::from_slice(&to_bytes(response.into_body()).await.unwrap()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_client_errors_keep_their_detail() {
+        let body = body_of(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })
+        .await;
+
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["title"], "Not Found");
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["detail"], "User not found");
+        assert!(body.get("correlation_id").is_none());
+        assert_eq!(
+            ServiceError::NotFound {
+                error_message: "User not found".to_string()
+            }
+            .to_string(),
+            "User not found"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_internal_errors_hide_their_cause() {
+        let err = ServiceError::from(DbErr::Custom("table user_info is gone".to_string()));
+        let body = body_of(err).await;
+
+        assert_eq!(body["status"], 500);
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["detail"], "An internal error occurred");
+        assert!(!body.to_string().contains("user_info"));
+        assert!(body["correlation_id"].as_str().is_some_and(|id| !id.is_empty()));
+    }
+
+    #[actix_rt::test]
+    async fn test_validation_errors_list_fields() {
+        let body = body_of(ServiceError::ValidationFailed {
+            error_message: "Profile is invalid".to_string(),
+            errors: FieldErrors::from([("age".to_string(), "must be between 0 and 150".to_string())]),
+        })
+        .await;
+
+        assert_eq!(body["status"], 422);
+        assert_eq!(body["errors"]["age"], "must be between 0 and 150");
+    }
+
+    #[test]
+    fn test_jwt_errors_map_to_client_errors() {
+        let expired = jsonwebtoken::errors::Error::from(ErrorKind::ExpiredSignature);
+        assert_eq!(ServiceError::from(expired).code(), "token_expired");
+
+        let garbage = jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken);
+        assert_eq!(ServiceError::from(garbage).code(), "malformed_token");
+
+        let early = jsonwebtoken::errors::Error::from(ErrorKind::ImmatureSignature);
+        assert_eq!(ServiceError::from(early).code(), "token_not_yet_valid");
+
+        let foreign = jsonwebtoken::errors::Error::from(ErrorKind::InvalidAudience);
+        assert_eq!(ServiceError::from(foreign).status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_unauthorized_responses_carry_a_challenge() {
+        let missing = ServiceError::Unauthorized {
+            error_message: "Unauthorized".to_string(),
+        }
+        .error_response();
+        assert_eq!(
+            missing.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            "Bearer realm=\"rust_server\""
+        );
+
+        let expired = ServiceError::TokenExpired {
+            error_message: "Token has expired".to_string(),
+        }
+        .error_response();
+        assert_eq!(
+            expired.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            "Bearer realm=\"rust_server\", error=\"invalid_token\", error_description=\"Token has expired\""
+        );
+
+        let forbidden = ServiceError::Forbidden {
+            error_message: "Forbidden".to_string(),
+        }
+        .error_response();
+        assert!(forbidden.headers().get(header::WWW_AUTHENTICATE).is_none());
+    }
+
+    #[test]
+    fn test_retry_after_header() {
+        let response = ServiceError::TooManyRequests {
+            error_message: "Too many failed login attempts".to_string(),
+            retry_after_seconds: 30,
+        }
+        .error_response();
+
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "30");
+    }
+}
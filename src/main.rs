@@ -1,11 +1,10 @@
 use actix_web::{middleware::Logger, web, App, HttpServer};
 use sea_orm::{Database, DatabaseConnection};
 
-use crate::utils::app_state::AppState;
-
-mod error;
-mod routes;
-mod utils;
+use rust_server::utils::app_state::AppState;
+use rust_server::utils::login_throttle::LoginThrottle;
+use rust_server::utils::revocation::RevocationStore;
+use rust_server::{error, routes, utils};
 
 #[actix_web::main]
 async fn main() -> Result<(), error::ServiceError> {
@@ -17,7 +16,7 @@ async fn main() -> Result<(), error::ServiceError> {
     dotenv::dotenv().ok();
     env_logger::init();
 
-    let port = (utils::constants::PORT).clone();
+    let port = *utils::constants::PORT;
     let address = (utils::constants::ADDRESS).clone();
     let database_url = (utils::constants::DATABASE_URL).clone();
 
@@ -29,18 +28,73 @@ async fn main() -> Result<(), error::ServiceError> {
         }
     })?;
 
+    // fail fast on a bad key configuration instead of on the first login
+    lazy_static::initialize(&utils::jwt_keys::KEYS);
+
+    let revocations = RevocationStore::load(&db).await.map_err(|err| {
+        error::ServiceError::DBConnectionError {
+            error_message: err.to_string(),
+        }
+    })?;
+
+    let mailer = utils::mailer::from_config().map_err(|err| {
+        error::ServiceError::InternalServerError {
+            error_message: err.to_string(),
+        }
+    })?;
+
+    let trusted_issuers = utils::trusted_issuers::TrustedIssuers::from_config().map_err(|err| {
+        error::ServiceError::InternalServerError {
+            error_message: err.to_string(),
+        }
+    })?;
+
+    let blobs = utils::blob_store::from_config().map_err(|err| {
+        error::ServiceError::InternalServerError {
+            error_message: err.to_string(),
+        }
+    })?;
+
+    let download_links = utils::blob_store::DownloadSigner::from_config().map_err(|err| {
+        error::ServiceError::InternalServerError {
+            error_message: err.to_string(),
+        }
+    })?;
+
+    let mfa_secrets = utils::mfa::SecretCipher::from_config().map_err(|error_message| {
+        error::ServiceError::InternalServerError { error_message }
+    })?;
+
     println!("Starting server on {}", address);
 
-    let app_state = web::Data::new(AppState { db });
-    
+    let app_state = web::Data::new(AppState {
+        db,
+        revocations,
+        login_throttle: LoginThrottle::new(),
+        mailer,
+        trusted_issuers,
+        blobs,
+        download_links,
+        mfa_secrets,
+    });
+
+    actix_web::rt::spawn(utils::personal_data::purge_periodically(app_state.clone()));
+
     HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
+            .app_data(web::JsonConfig::default().error_handler(error::extractor_error))
+            .app_data(web::FormConfig::default().error_handler(error::extractor_error))
+            .app_data(web::QueryConfig::default().error_handler(error::extractor_error))
+            .app_data(web::PathConfig::default().error_handler(error::extractor_error))
             .wrap(Logger::default())
             .configure(routes::user_routes::config)
             .configure(routes::auth_routes::config)
             .configure(routes::block_routes::config)
             .configure(routes::tx_routes::config)
+            .configure(routes::well_known_routes::config)
+            .configure(routes::admin_routes::config)
+            .configure(routes::blob_routes::config)
     })
     .bind((address, port))
     .map_err(|err| error::ServiceError::BindAddressError {
@@ -0,0 +1,29 @@
+use actix_web::web;
+use actix_web_lab::middleware::from_fn;
+
+use crate::routes::middlewares;
+use crate::routes::middlewares::role_middleware::Authorize;
+use crate::utils::roles::Permission;
+
+use super::handlers::{admin_handlers, oauth_handlers};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            // runs after the auth middleware, which is registered last
+            .wrap(Authorize::permission(Permission::UserAdmin))
+            .wrap(from_fn(middlewares::auth_middleware::check_auth_middleware))
+            .service(admin_handlers::search_users)
+            .service(admin_handlers::view_user)
+            .service(admin_handlers::disable_user)
+            .service(admin_handlers::enable_user)
+            .service(admin_handlers::force_password_reset)
+            .service(admin_handlers::change_role)
+            .service(admin_handlers::delete_user)
+            .service(admin_handlers::restore_user)
+            .service(admin_handlers::unlock_user)
+            .service(oauth_handlers::create_client)
+            .service(oauth_handlers::list_clients)
+            .service(oauth_handlers::revoke_client),
+    );
+}
@@ -1,7 +1,26 @@
 use actix_web::web;
+use actix_web_lab::middleware::from_fn;
 
-use super::handlers::auth_handlers;      
+use super::handlers::{account_handlers, auth_handlers, oauth_handlers};
+use super::middlewares::auth_middleware;
 
 pub fn config(config: &mut web::ServiceConfig) {
-    config.service(web::scope("/auth").service(auth_handlers::login));
+    config.service(
+        web::scope("/auth")
+            .service(auth_handlers::login)
+            .service(auth_handlers::refresh)
+            .service(auth_handlers::verify_mfa)
+            .service(account_handlers::verify_email)
+            .service(account_handlers::request_password_reset)
+            .service(account_handlers::reset_password)
+            .service(auth_handlers::siwe_nonce)
+            .service(auth_handlers::siwe_login)
+            .service(oauth_handlers::token)
+            .service(
+                web::scope("")
+                    .wrap(from_fn(auth_middleware::check_auth_middleware))
+                    .service(auth_handlers::logout)
+                    .service(auth_handlers::logout_all),
+            ),
+    );
 }
@@ -0,0 +1,7 @@
+use actix_web::web;
+
+use super::handlers::blob_handlers;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/blobs").service(blob_handlers::download_blob));
+}
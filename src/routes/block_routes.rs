@@ -2,6 +2,8 @@ use actix_web::web;
 use actix_web_lab::middleware::from_fn;
 
 use crate::routes::middlewares;
+use crate::routes::middlewares::role_middleware::Authorize;
+use crate::utils::roles::Permission;
 
 use super::handlers::block_handlers;
 
@@ -9,6 +11,8 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     cfg
     .service(
         web::scope("secure/block")
+            // runs after the auth middleware, which is registered last
+            .wrap(Authorize::permission(Permission::BlockWrite))
             .wrap(from_fn(middlewares::auth_middleware::check_auth_middleware))
             .service(block_handlers::create_block),
     )
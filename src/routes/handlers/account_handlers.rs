@@ -0,0 +1,208 @@
+use actix_web::{get, post, rt, web};
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::error::ServiceError;
+use crate::utils::action_token::{self, ActionPurpose, ActionTokenError};
+use crate::utils::mailer::Mail;
+use crate::utils::{
+    api_response, app_state, constants, jwt::Claims, password, session, validation,
+};
+
+#[derive(Serialize, Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PasswordResetRequestModel {
+    email: String,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+struct PasswordResetModel {
+    token: String,
+    #[validate(custom(function = "validation::password"))]
+    new_password: String,
+}
+
+fn action_token_error(err: ActionTokenError) -> ServiceError {
+    match err {
+        ActionTokenError::Invalid => ServiceError::BadRequest {
+            error_message: err.to_string(),
+        },
+        err => ServiceError::internal(err),
+    }
+}
+
+pub async fn send_verification_email(
+    app_state: &app_state::AppState,
+    user: &entities::user_info::Model,
+) -> Result<(), ServiceError> {
+    let token = action_token::issue_action_token(&app_state.db, user.id, ActionPurpose::VerifyEmail)
+        .await
+        .map_err(action_token_error)?;
+
+    app_state
+        .mailer
+        .send(Mail {
+            to: user.email.clone(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Hi {},\n\nconfirm your email address by opening this link:\n\n{}/auth/verify-email?token={}\n\nThe link expires in {} hours.\n",
+                user.name,
+                constants::APP_BASE_URL.trim_end_matches('/'),
+                token,
+                *constants::EMAIL_VERIFICATION_TTL_HOURS
+            ),
+        })
+        .await
+        .map_err(ServiceError::internal)
+}
+
+pub async fn send_password_reset_email(
+    app_state: &app_state::AppState,
+    user: &entities::user_info::Model,
+) -> Result<(), ServiceError> {
+    let token = action_token::issue_action_token(&app_state.db, user.id, ActionPurpose::ResetPassword)
+        .await
+        .map_err(action_token_error)?;
+
+    app_state
+        .mailer
+        .send(Mail {
+            to: user.email.clone(),
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hi {},\n\nsomeone asked to reset your password. If that was you, use this token to choose a new one:\n\n{}\n\nIt expires in {} minutes. If it was not you, ignore this email.\n",
+                user.name,
+                token,
+                *constants::PASSWORD_RESET_TTL_MINUTES
+            ),
+        })
+        .await
+        .map_err(ServiceError::internal)
+}
+
+#[get("/verify-email")]
+pub async fn verify_email(
+    app_state: web::Data<app_state::AppState>,
+    query: web::Query<TokenQuery>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let user_id =
+        action_token::consume_action_token(&app_state.db, &query.token, ActionPurpose::VerifyEmail)
+            .await
+            .map_err(action_token_error)?;
+
+    let mut user_model = entities::user_info::Entity::find_by_id(user_id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?
+        .into_active_model();
+
+    user_model.email_verified = Set(true);
+    user_model
+        .update(&app_state.db)
+        .await?;
+
+    Ok(api_response::ApiResponse::new(200, "Email verified".to_string()))
+}
+
+#[post("verify-email/resend")]
+pub async fn resend_verification_email(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let user = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    if user.email_verified {
+        return Err(ServiceError::Conflict {
+            error_message: "Email is already verified".to_string(),
+        });
+    }
+
+    send_verification_email(&app_state, &user).await?;
+
+    Ok(api_response::ApiResponse::new(200, "Verification email sent".to_string()))
+}
+
+#[post("/password-reset/request")]
+pub async fn request_password_reset(
+    app_state: web::Data<app_state::AppState>,
+    request_data: web::Json<PasswordResetRequestModel>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let user = entities::user_info::Entity::find()
+        .filter(entities::user_info::Column::Email.eq(&request_data.email))
+        .filter(entities::user_info::Column::DeletedAt.is_null())
+        .one(&app_state.db)
+        .await?;
+
+    if let Some(user) = user {
+        // sent in the background and failures only logged: neither the response nor
+        // its timing may reveal whether the account exists
+        let app_state = app_state.clone();
+        rt::spawn(async move {
+            if let Err(err) = send_password_reset_email(&app_state, &user).await {
+                log::error!("password reset mail for user {} failed: {}", user.id, err);
+            }
+        });
+    }
+
+    Ok(api_response::ApiResponse::new(
+        200,
+        "If the account exists, a reset email has been sent".to_string(),
+    ))
+}
+
+#[post("/password-reset")]
+pub async fn reset_password(
+    app_state: web::Data<app_state::AppState>,
+    reset_data: web::Json<PasswordResetModel>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    // checked before the token is consumed, so a rejected password leaves the link usable
+    validation::validate(&*reset_data)?;
+
+    let user_id = action_token::consume_action_token(
+        &app_state.db,
+        &reset_data.token,
+        ActionPurpose::ResetPassword,
+    )
+    .await
+    .map_err(action_token_error)?;
+
+    let new_password = reset_data.new_password.clone();
+    let new_hash = web::block(move || password::hash_password(&new_password))
+        .await?
+        .map_err(ServiceError::internal)?;
+
+    let mut user_model = entities::user_info::Entity::find_by_id(user_id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?
+        .into_active_model();
+
+    // the reset link proved control of the inbox, and lifts any lockout
+    user_model.password = Set(new_hash);
+    user_model.email_verified = Set(true);
+    user_model.failed_logins = Set(0);
+    user_model.locked_until = Set(None);
+    user_model
+        .update(&app_state.db)
+        .await?;
+
+    // a new password ends every existing login
+    session::end_all_logins(&app_state.db, &app_state.revocations, user_id)
+        .await?;
+
+    Ok(api_response::ApiResponse::new(200, "Password has been reset".to_string()))
+}
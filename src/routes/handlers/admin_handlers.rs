@@ -0,0 +1,460 @@
+use actix_web::{delete, get, post, put, web};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sea_orm::sea_query::{Expr, Func, LikeExpr, Query};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, IntoActiveModel, PaginatorTrait,
+    QueryFilter, QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::account_handlers;
+use crate::error::ServiceError;
+use crate::utils::audit::{self, AuditAction};
+use crate::utils::jwt::{Claims, Principal};
+use crate::utils::roles::Role;
+use crate::utils::{api_response, app_state, eth, password, personal_data, session, wallet};
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+const HISTORY_LIMIT: u64 = 50;
+
+#[derive(Serialize, Deserialize)]
+struct UserSearchQuery {
+    /// Part of an email, a name or a wallet address.
+    q: Option<String>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChangeRoleModel {
+    role: Role,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AdminUserModel {
+    id: i32,
+    name: String,
+    email: String,
+    email_verified: bool,
+    role: String,
+    wallet_address: String,
+    failed_logins: i32,
+    locked_until: Option<DateTime<Utc>>,
+    disabled_at: Option<DateTime<Utc>>,
+    deleted_at: Option<DateTime<Utc>>,
+    purged_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<entities::user_info::Model> for AdminUserModel {
+    fn from(user: entities::user_info::Model) -> Self {
+        AdminUserModel {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            email_verified: user.email_verified,
+            role: user.role,
+            wallet_address: user.wallet_address,
+            failed_logins: user.failed_logins,
+            locked_until: user.locked_until,
+            disabled_at: user.disabled_at,
+            deleted_at: user.deleted_at,
+            purged_at: user.purged_at,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AdminWalletModel {
+    id: i32,
+    chain_id: i64,
+    address: String,
+    label: String,
+    verified_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuditEntryModel {
+    actor: String,
+    action: String,
+    details: serde_json::Value,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AdminUserDetailModel {
+    #[serde(flatten)]
+    user: AdminUserModel,
+    wallets: Vec<AdminWalletModel>,
+    history: Vec<AuditEntryModel>,
+}
+
+async fn find_user(
+    app_state: &app_state::AppState,
+    user_id: i32,
+) -> Result<entities::user_info::Model, ServiceError> {
+    entities::user_info::Entity::find_by_id(user_id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })
+}
+
+/// Admins must not lock themselves out; another admin has to do it.
+fn reject_self(claims: &Claims, user_id: i32) -> Result<(), ServiceError> {
+    if claims.principal() == Principal::User(user_id) {
+        return Err(ServiceError::BadRequest {
+            error_message: "Admins cannot change their own account here".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+async fn record_audit(
+    app_state: &app_state::AppState,
+    claims: &Claims,
+    action: AuditAction,
+    user_id: i32,
+    details: serde_json::Value,
+) -> Result<(), ServiceError> {
+    audit::record(&app_state.db, claims, action, user_id, details)
+        .await
+        .map(|_| ())
+        .map_err(ServiceError::internal)
+}
+
+fn user_response(
+    status_code: u16,
+    user: entities::user_info::Model,
+) -> Result<api_response::ApiResponse<AdminUserModel>, ServiceError> {
+    Ok(api_response::ApiResponse::with_data(status_code, AdminUserModel::from(user)))
+}
+
+/// `LIKE` pattern for `value` anywhere in a column. `%` and `_` typed by the
+/// admin match themselves instead of acting as wildcards.
+fn contains_pattern(value: &str) -> LikeExpr {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+
+    LikeExpr::new(format!("%{}%", escaped)).escape('\\')
+}
+
+#[get("users")]
+pub async fn search_users(
+    app_state: web::Data<app_state::AppState>,
+    query: web::Query<UserSearchQuery>,
+) -> Result<api_response::ApiResponse<Vec<AdminUserModel>>, ServiceError> {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut select = entities::user_info::Entity::find();
+
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        // addresses are stored lowercased in user_wallet, but as typed in user_info
+        let address = q.to_lowercase();
+
+        select = select.filter(
+            Condition::any()
+                .add(
+                    Expr::col((entities::user_info::Entity, entities::user_info::Column::Email))
+                        .like(contains_pattern(q)),
+                )
+                .add(
+                    Expr::col((entities::user_info::Entity, entities::user_info::Column::Name))
+                        .like(contains_pattern(q)),
+                )
+                .add(
+                    Expr::expr(Func::lower(Expr::col(entities::user_info::Column::WalletAddress)))
+                        .like(contains_pattern(&address)),
+                )
+                .add(
+                    entities::user_info::Column::Id.in_subquery(
+                        Query::select()
+                            .column(entities::user_wallet::Column::UserId)
+                            .from(entities::user_wallet::Entity)
+                            .and_where(
+                                Expr::col((entities::user_wallet::Entity, entities::user_wallet::Column::Address))
+                                    .like(contains_pattern(&address)),
+                            )
+                            .to_owned(),
+                    ),
+                ),
+        );
+    }
+
+    let paginator = select
+        .order_by_asc(entities::user_info::Column::Id)
+        .paginate(&app_state.db, per_page);
+
+    let total = paginator
+        .num_items()
+        .await?;
+
+    let users = paginator
+        .fetch_page(page - 1)
+        .await?
+        .into_iter()
+        .map(AdminUserModel::from)
+        .collect::<Vec<AdminUserModel>>();
+
+    Ok(api_response::ApiResponse::ok(users).with_meta(json!({
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+    })))
+}
+
+#[get("users/{user_id}")]
+pub async fn view_user(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+) -> Result<api_response::ApiResponse<AdminUserDetailModel>, ServiceError> {
+    let user = find_user(&app_state, user_id.into_inner()).await?;
+
+    let wallets = wallet::user_wallets(&app_state.db, user.id)
+        .await?
+        .into_iter()
+        .map(|wallet| AdminWalletModel {
+            id: wallet.id,
+            chain_id: wallet.chain_id,
+            address: eth::to_checksum_address(&wallet.address).unwrap_or(wallet.address),
+            label: wallet.label,
+            verified_at: wallet.verified_at,
+        })
+        .collect();
+
+    let history = audit::user_history(&app_state.db, user.id, HISTORY_LIMIT)
+        .await?
+        .into_iter()
+        .map(|entry| AuditEntryModel {
+            actor: entry.actor,
+            action: entry.action,
+            details: serde_json::from_str(&entry.details).unwrap_or_default(),
+            created_at: entry.created_at,
+        })
+        .collect();
+
+    Ok(api_response::ApiResponse::ok(AdminUserDetailModel {
+        user: AdminUserModel::from(user),
+        wallets,
+        history,
+    }))
+}
+
+#[post("users/{user_id}/disable")]
+pub async fn disable_user(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<AdminUserModel>, ServiceError> {
+    let user_id = user_id.into_inner();
+    reject_self(&claims, user_id)?;
+
+    let user = find_user(&app_state, user_id).await?;
+    if user.disabled_at.is_some() {
+        return user_response(200, user);
+    }
+
+    let mut user_model = user.into_active_model();
+    user_model.disabled_at = Set(Some(Utc::now()));
+    let user = user_model
+        .update(&app_state.db)
+        .await?;
+
+    session::end_all_logins(&app_state.db, &app_state.revocations, user_id)
+        .await?;
+
+    record_audit(&app_state, &claims, AuditAction::DisableUser, user_id, json!({})).await?;
+
+    user_response(200, user)
+}
+
+#[post("users/{user_id}/enable")]
+pub async fn enable_user(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<AdminUserModel>, ServiceError> {
+    let user_id = user_id.into_inner();
+
+    let user = find_user(&app_state, user_id).await?;
+    if user.disabled_at.is_none() {
+        return user_response(200, user);
+    }
+
+    let mut user_model = user.into_active_model();
+    user_model.disabled_at = Set(None);
+    let user = user_model
+        .update(&app_state.db)
+        .await?;
+
+    record_audit(&app_state, &claims, AuditAction::EnableUser, user_id, json!({})).await?;
+
+    user_response(200, user)
+}
+
+/// Invalidates the password and every login, then mails the user a reset token.
+#[post("users/{user_id}/password-reset")]
+pub async fn force_password_reset(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let user_id = user_id.into_inner();
+
+    let user = find_user(&app_state, user_id).await?;
+    if user.deleted_at.is_some() {
+        return Err(ServiceError::Conflict {
+            error_message: "User has been deleted".to_string(),
+        });
+    }
+
+    // nothing verifies against the marker, so only the reset token gets the user back in
+    let mut user_model = user.into_active_model();
+    user_model.password = Set(password::RESET_REQUIRED.to_string());
+    let user = user_model
+        .update(&app_state.db)
+        .await?;
+
+    session::end_all_logins(&app_state.db, &app_state.revocations, user_id)
+        .await?;
+
+    record_audit(&app_state, &claims, AuditAction::ForcePasswordReset, user_id, json!({})).await?;
+
+    account_handlers::send_password_reset_email(&app_state, &user).await?;
+
+    Ok(api_response::ApiResponse::new(
+        200,
+        "Password reset email sent".to_string(),
+    ))
+}
+
+#[put("users/{user_id}/role")]
+pub async fn change_role(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+    role_data: web::Json<ChangeRoleModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<AdminUserModel>, ServiceError> {
+    let user_id = user_id.into_inner();
+    reject_self(&claims, user_id)?;
+
+    let user = find_user(&app_state, user_id).await?;
+    let previous_role = user.role.clone();
+    if previous_role == role_data.role.as_str() {
+        return user_response(200, user);
+    }
+
+    let mut user_model = user.into_active_model();
+    user_model.role = Set(role_data.role.as_str().to_owned());
+    let user = user_model
+        .update(&app_state.db)
+        .await?;
+
+    // access tokens carry the role, so outstanding ones must not keep the old one
+    app_state
+        .revocations
+        .bump_token_version(&app_state.db, user_id)
+        .await?;
+
+    record_audit(
+        &app_state,
+        &claims,
+        AuditAction::ChangeRole,
+        user_id,
+        json!({ "from": previous_role, "to": role_data.role.as_str() }),
+    )
+    .await?;
+
+    user_response(200, user)
+}
+
+#[delete("users/{user_id}")]
+pub async fn delete_user(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<AdminUserModel>, ServiceError> {
+    let user_id = user_id.into_inner();
+    reject_self(&claims, user_id)?;
+
+    let user = find_user(&app_state, user_id).await?;
+    if user.deleted_at.is_some() {
+        return user_response(200, user);
+    }
+
+    let user = personal_data::soft_delete(&app_state.db, &app_state.revocations, user)
+        .await?;
+
+    record_audit(&app_state, &claims, AuditAction::DeleteUser, user_id, json!({})).await?;
+
+    user_response(202, user)
+}
+
+/// Undoes a deletion during the grace period.
+#[post("users/{user_id}/restore")]
+pub async fn restore_user(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<AdminUserModel>, ServiceError> {
+    let user_id = user_id.into_inner();
+
+    let user = find_user(&app_state, user_id).await?;
+    if user.purged_at.is_some() {
+        return Err(ServiceError::Conflict {
+            error_message: "User has already been purged".to_string(),
+        });
+    }
+    if user.deleted_at.is_none() {
+        return user_response(200, user);
+    }
+
+    let mut user_model = user.into_active_model();
+    user_model.deleted_at = Set(None);
+    let user = user_model
+        .update(&app_state.db)
+        .await?;
+
+    record_audit(&app_state, &claims, AuditAction::RestoreUser, user_id, json!({})).await?;
+
+    user_response(200, user)
+}
+
+#[post("users/{user_id}/unlock")]
+pub async fn unlock_user(
+    app_state: web::Data<app_state::AppState>,
+    user_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let user_id = user_id.into_inner();
+
+    let result = entities::user_info::Entity::update_many()
+        .col_expr(entities::user_info::Column::FailedLogins, Expr::value(0))
+        .col_expr(
+            entities::user_info::Column::LockedUntil,
+            Expr::value(Option::<chrono::DateTime<chrono::Utc>>::None),
+        )
+        .filter(entities::user_info::Column::Id.eq(user_id))
+        .exec(&app_state.db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        });
+    }
+
+    record_audit(&app_state, &claims, AuditAction::UnlockUser, user_id, json!({})).await?;
+
+    Ok(api_response::ApiResponse::new(200, "User unlocked".to_string()))
+}
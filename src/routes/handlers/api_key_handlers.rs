@@ -0,0 +1,145 @@
+use actix_web::{delete, get, post, web};
+use chrono::{Duration, NaiveDateTime, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServiceError;
+use crate::utils::roles::Permission;
+use crate::utils::{api_key, api_response, app_state, jwt::Claims};
+
+#[derive(Serialize, Deserialize)]
+struct CreateApiKeyModel {
+    name: String,
+    scopes: Vec<Permission>,
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ApiKeyModel {
+    pub id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Permission>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CreatedApiKeyModel {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKeyModel,
+}
+
+impl From<entities::api_key::Model> for ApiKeyModel {
+    fn from(key: entities::api_key::Model) -> Self {
+        ApiKeyModel {
+            id: key.id,
+            name: key.name,
+            prefix: key.prefix,
+            scopes: api_key::parse_scopes(&key.scopes).unwrap_or_default(),
+            expires_at: key.expires_at,
+            last_used_at: key.last_used_at,
+            revoked_at: key.revoked_at,
+            created_at: key.created_at,
+        }
+    }
+}
+
+#[post("api-keys")]
+pub async fn create_api_key(
+    app_state: web::Data<app_state::AppState>,
+    key_data: web::Json<CreateApiKeyModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<CreatedApiKeyModel>, ServiceError> {
+    if key_data.name.trim().is_empty() || key_data.name.len() > 100 {
+        return Err(ServiceError::BadRequest {
+            error_message: "Name must be between 1 and 100 characters".to_string(),
+        });
+    }
+
+    if let Some(scope) = key_data
+        .scopes
+        .iter()
+        .find(|scope| !claims.role.has_permission(**scope))
+    {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("Scope not allowed for your role: {}", scope.as_str()),
+        });
+    }
+
+    if key_data.expires_in_days.is_some_and(|days| days <= 0) {
+        return Err(ServiceError::BadRequest {
+            error_message: "expires_in_days must be positive".to_string(),
+        });
+    }
+
+    let now = Utc::now().naive_utc();
+    let generated = api_key::generate_api_key();
+
+    let key_model = entities::api_key::ActiveModel {
+        user_id: Set(claims.id),
+        name: Set(key_data.name.trim().to_owned()),
+        prefix: Set(generated.prefix),
+        key_hash: Set(generated.hash),
+        scopes: Set(api_key::format_scopes(&key_data.scopes)),
+        expires_at: Set(key_data.expires_in_days.map(|days| now + Duration::days(days))),
+        last_used_at: Set(None),
+        revoked_at: Set(None),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&app_state.db)
+    .await?;
+
+    Ok(api_response::ApiResponse::created(CreatedApiKeyModel {
+        key: generated.key,
+        api_key: key_model.into(),
+    }))
+}
+
+#[get("api-keys")]
+pub async fn list_api_keys(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<Vec<ApiKeyModel>>, ServiceError> {
+    let keys = entities::api_key::Entity::find()
+        .filter(entities::api_key::Column::UserId.eq(claims.id))
+        .order_by_desc(entities::api_key::Column::CreatedAt)
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(ApiKeyModel::from)
+        .collect::<Vec<ApiKeyModel>>();
+
+    Ok(api_response::ApiResponse::ok(keys))
+}
+
+#[delete("api-keys/{key_id}")]
+pub async fn revoke_api_key(
+    app_state: web::Data<app_state::AppState>,
+    key_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let result = entities::api_key::Entity::update_many()
+        .col_expr(
+            entities::api_key::Column::RevokedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(entities::api_key::Column::Id.eq(key_id.into_inner()))
+        .filter(entities::api_key::Column::UserId.eq(claims.id))
+        .filter(entities::api_key::Column::RevokedAt.is_null())
+        .exec(&app_state.db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(ServiceError::NotFound {
+            error_message: "API key not found".to_string(),
+        });
+    }
+
+    Ok(api_response::ApiResponse::new(200, "API key revoked".to_string()))
+}
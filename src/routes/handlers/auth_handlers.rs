@@ -1,14 +1,24 @@
+use crate::error::ServiceError;
 use crate::utils::api_response::ApiResponse;
-use crate::utils::jwt::encode_jwt;
-use crate::utils::{api_response, app_state};
-use actix_web::{post, web};
+use crate::utils::jwt::{decode_jwt, encode_jwt, encode_mfa_token, Claims, TokenType};
+use crate::utils::password::{self, PasswordCheck};
+use crate::utils::refresh_token::{self, RefreshError};
+use crate::utils::session::{self, ClientInfo};
+use crate::utils::siwe::SiweMessage;
+use crate::utils::auth_cookie::{self, AuthMode};
+use crate::utils::{api_response, app_state, constants, login_throttle, mfa, nonce, wallet};
+use actix_web::{get, post, web, HttpRequest};
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::ActiveModelTrait;
 use sea_orm::ColumnTrait;
 use sea_orm::Condition;
 use sea_orm::EntityTrait;
+use sea_orm::IntoActiveModel;
 use sea_orm::QueryFilter;
+use sea_orm::Set;
 use serde::Deserialize;
 use serde::Serialize;
-use sha256::digest;
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize)]
 struct LoginModel {
@@ -16,29 +26,529 @@ struct LoginModel {
     password: String,
 }
 
+#[derive(Serialize, Deserialize)]
+struct RefreshModel {
+    refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CookieSessionModel {
+    token_type: String,
+    expires_in: i64,
+    csrf_token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogoutModel {
+    refresh_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SiweModel {
+    message: String,
+    signature: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NonceModel {
+    nonce: String,
+    expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MfaModel {
+    mfa_token: String,
+    code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MfaRequiredModel {
+    mfa_required: bool,
+    mfa_token: String,
+    expires_in: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TokenModel {
+    access_token: String,
+    refresh_token: String,
+    token_type: String,
+    expires_in: i64,
+}
+
+/// Body of a login step; cookie sessions never see the tokens.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum SessionModel {
+    Cookie(CookieSessionModel),
+    Bearer(TokenModel),
+    MfaRequired(MfaRequiredModel),
+}
+
+fn token_response(
+    access_token: String,
+    refresh_token: String,
+    mode: AuthMode,
+) -> Result<ApiResponse<SessionModel>, ServiceError> {
+    if mode == AuthMode::Cookie {
+        let csrf_token = auth_cookie::generate_csrf_token();
+        let session = SessionModel::Cookie(CookieSessionModel {
+            token_type: "Cookie".to_owned(),
+            expires_in: *constants::ACCESS_TOKEN_TTL_MINUTES * 60,
+            csrf_token: csrf_token.clone(),
+        });
+
+        return Ok(auth_cookie::session_cookies(access_token, refresh_token, csrf_token)
+            .into_iter()
+            .fold(ApiResponse::ok(session), |response, cookie| {
+                response.with_header("Set-Cookie", cookie.to_string())
+            }));
+    }
+
+    Ok(ApiResponse::ok(SessionModel::Bearer(TokenModel {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_owned(),
+        expires_in: *constants::ACCESS_TOKEN_TTL_MINUTES * 60,
+    })))
+}
+
+/// Disabled accounts keep their data but cannot start new sessions.
+fn check_account_enabled(user_data: &entities::user_info::Model) -> Result<(), ServiceError> {
+    if user_data.disabled_at.is_some() {
+        return Err(ServiceError::AccountDisabled {
+            error_message: "Account is disabled".to_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Finishes the first login step: accounts with MFA get an mfa pending token, everyone else a session.
+async fn login_response(
+    app_state: &app_state::AppState,
+    user_data: &entities::user_info::Model,
+    req: &HttpRequest,
+) -> Result<ApiResponse<SessionModel>, ServiceError> {
+    // checked after the credentials, so the answer does not reveal whether an account exists
+    check_account_enabled(user_data)?;
+
+    let mfa_enabled = mfa::find_enabled(&app_state.db, user_data.id)
+        .await?
+        .is_some();
+
+    if !mfa_enabled {
+        return issue_session(app_state, user_data, req).await;
+    }
+
+    let mfa_token = encode_mfa_token(user_data)?;
+
+    Ok(ApiResponse::ok(SessionModel::MfaRequired(MfaRequiredModel {
+        mfa_required: true,
+        mfa_token,
+        expires_in: *constants::MFA_TOKEN_TTL_MINUTES * 60,
+    })))
+}
+
+/// Starts a new session for a fully authenticated user: a fresh refresh token family plus an access token.
+async fn issue_session(
+    app_state: &app_state::AppState,
+    user_data: &entities::user_info::Model,
+    req: &HttpRequest,
+) -> Result<ApiResponse<SessionModel>, ServiceError> {
+    let family_id = Uuid::new_v4().to_string();
+    let (refresh_token, _) = refresh_token::issue_refresh_token(&app_state.db, user_data.id, &family_id)
+        .await?;
+
+    let client = ClientInfo::from_request(req);
+    let user_session = session::create_session(&app_state.db, user_data.id, &family_id, &client)
+        .await?;
+
+    // generate jwt token and return it
+    let token = encode_jwt(user_data, Some(user_session.id))?;
+
+    token_response(token, refresh_token, AuthMode::from_query(req.query_string()))
+}
+
 #[post("/login")]
 pub async fn login(
+    req: HttpRequest,
     app_state: web::Data<app_state::AppState>,
     login_json: web::Json<LoginModel>,
-) -> Result<ApiResponse, ApiResponse> {
+) -> Result<ApiResponse<SessionModel>, ServiceError> {
+    // the socket peer, not X-Forwarded-For, which any client can set
+    let client_ip = req.peer_addr().map(|addr| addr.ip());
+    login_throttle::check_attempt(&app_state, client_ip, None)?;
+
     let user_data = entities::user_info::Entity::find()
-        .filter(
-            Condition::all()
-                .add(entities::user_info::Column::Email.eq(&login_json.email))
-                .add(entities::user_info::Column::Password.eq(digest(&login_json.password))),
-        )
+        .filter(Condition::all().add(entities::user_info::Column::Email.eq(&login_json.email)))
+        .filter(entities::user_info::Column::DeletedAt.is_null())
         .one(&app_state.db)
-        .await
-        .map_err(|err| ApiResponse::new(500, err.to_string()))?
-        .ok_or(ApiResponse::new(404, "User Not Found".to_owned()))?;
+        .await?;
 
-    // generate jwt token and return it
-    let token = encode_jwt(user_data.email, user_data.id)
-        .map_err(|err| ApiResponse::new(500, err.to_string()))?;
+    let mut user_data = match user_data {
+        Some(user_data) => user_data,
+        None => {
+            let password = login_json.password.clone();
+            web::block(move || password::verify_dummy(&password)).await?;
+
+            login_throttle::record_failed_attempt(&app_state, client_ip, None).await?;
+            return Err(ServiceError::NotFound {
+                error_message: "User Not Found".to_owned(),
+            });
+        }
+    };
+
+    login_throttle::check_attempt(&app_state, None, Some(&user_data))?;
+
+    // argon2 is deliberately slow, keep it off the async workers
+    let password = login_json.password.clone();
+    let stored_hash = user_data.password.clone();
+    let password_check = web::block(move || password::verify_password(&password, &stored_hash))
+        .await?;
+
+    match password_check {
+        PasswordCheck::Invalid => {
+            login_throttle::record_failed_attempt(&app_state, client_ip, Some(&user_data)).await?;
+            return Err(ServiceError::NotFound {
+                error_message: "User Not Found".to_owned(),
+            });
+        }
+        PasswordCheck::ValidNeedsRehash => {
+            let password = login_json.password.clone();
+            let new_hash = web::block(move || password::hash_password(&password))
+                .await?
+                .map_err(ServiceError::internal)?;
+
+            let mut user_model = user_data.into_active_model();
+            user_model.password = Set(new_hash);
+            user_data = user_model
+                .update(&app_state.db)
+                .await?;
+        }
+        PasswordCheck::Valid => {}
+    }
+
+    let user_data = login_throttle::reset_failed_attempts(&app_state, user_data).await?;
+
+    // every login starts a new refresh token family
+    login_response(&app_state, &user_data, &req).await
+}
+
+#[post("/mfa")]
+pub async fn verify_mfa(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    mfa_json: web::Json<MfaModel>,
+) -> Result<ApiResponse<SessionModel>, ServiceError> {
+    let client_ip = req.peer_addr().map(|addr| addr.ip());
+    login_throttle::check_attempt(&app_state, client_ip, None)?;
+
+    let claims = decode_jwt(mfa_json.mfa_token.clone())
+        .map_err(|_| ServiceError::Unauthorized {
+            error_message: "Invalid MFA token".to_owned(),
+        })?
+        .claims;
+
+    if claims.typ != TokenType::MfaPending {
+        return Err(ServiceError::Unauthorized {
+            error_message: "Invalid MFA token".to_owned(),
+        });
+    }
+
+    let token_valid = app_state
+        .revocations
+        .is_token_valid(&app_state.db, &claims)
+        .await?;
 
-    Ok(api_response::ApiResponse::new(
-        200,
-        format!("{{ 'token':'{}' }}", token),
+    if !token_valid {
+        return Err(ServiceError::Unauthorized {
+            error_message: "Invalid MFA token".to_owned(),
+        });
+    }
+
+    let user_data = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::Unauthorized {
+            error_message: "Invalid MFA token".to_owned(),
+        })?;
+
+    // a six digit code falls quickly to unthrottled guessing
+    login_throttle::check_attempt(&app_state, None, Some(&user_data))?;
+    check_account_enabled(&user_data)?;
+
+    let user_mfa = mfa::find_enabled(&app_state.db, user_data.id)
+        .await?
+        .ok_or(ServiceError::Unauthorized {
+            error_message: "MFA is not enabled".to_owned(),
+        })?;
+
+    let code_valid = mfa::verify_second_factor(
+        &app_state.db,
+        &app_state.mfa_secrets,
+        &user_mfa,
+        &user_data.email,
+        &mfa_json.code,
+    )
+    .await?;
+
+    if !code_valid {
+        login_throttle::record_failed_attempt(&app_state, client_ip, Some(&user_data)).await?;
+        return Err(ServiceError::Unauthorized {
+            error_message: "Invalid code".to_owned(),
+        });
+    }
+
+    let user_data = login_throttle::reset_failed_attempts(&app_state, user_data).await?;
+
+    // an mfa pending token is good for exactly one session
+    app_state
+        .revocations
+        .revoke(&app_state.db, &claims)
+        .await?;
+
+    issue_session(&app_state, &user_data, &req).await
+}
+
+#[get("/nonce")]
+pub async fn siwe_nonce(
+    app_state: web::Data<app_state::AppState>,
+) -> Result<ApiResponse<NonceModel>, ServiceError> {
+    let (nonce, expires_at) = nonce::issue_nonce(&app_state.db)
+        .await?;
+
+    Ok(ApiResponse::ok(NonceModel { nonce, expires_at }))
+}
+
+#[post("/siwe")]
+pub async fn siwe_login(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    siwe_json: web::Json<SiweModel>,
+) -> Result<ApiResponse<SessionModel>, ServiceError> {
+    let message = siwe_json
+        .message
+        .parse::<SiweMessage>()
+        .map_err(|err| ServiceError::BadRequest {
+            error_message: err.to_string(),
+        })?;
+
+    if message.domain != *constants::SIWE_DOMAIN {
+        return Err(ServiceError::Unauthorized {
+            error_message: "SIWE domain mismatch".to_owned(),
+        });
+    }
+
+    if message.uri != *constants::APP_BASE_URL {
+        return Err(ServiceError::Unauthorized {
+            error_message: "SIWE URI mismatch".to_owned(),
+        });
+    }
+
+    if message.chain_id != *constants::SIWE_CHAIN_ID {
+        return Err(ServiceError::Unauthorized {
+            error_message: "SIWE chain ID mismatch".to_owned(),
+        });
+    }
+
+    let signature_valid = message
+        .verify(&siwe_json.message, &siwe_json.signature, Utc::now())
+        .map_err(|err| ServiceError::Unauthorized {
+            error_message: err.to_string(),
+        })?;
+
+    if !signature_valid {
+        return Err(ServiceError::Unauthorized {
+            error_message: "Invalid signature".to_owned(),
+        });
+    }
+
+    // burn the nonce only once the signature checks out, so garbage cannot exhaust it
+    let nonce_valid = nonce::consume_nonce(&app_state.db, &message.nonce)
+        .await?;
+
+    if !nonce_valid {
+        return Err(ServiceError::Unauthorized {
+            error_message: "Invalid or expired nonce".to_owned(),
+        });
+    }
+
+    // only wallets linked through a signed challenge count, and they must name one account
+    let user_id = match wallet::linked_user_ids(&app_state.db, &message.address).await?.as_slice() {
+        [user_id] => *user_id,
+        [] => {
+            return Err(ServiceError::NotFound {
+                error_message: "User Not Found".to_owned(),
+            })
+        }
+        _ => {
+            return Err(ServiceError::Conflict {
+                error_message: "Wallet is linked to more than one account, sign in with email".to_owned(),
+            })
+        }
+    };
+
+    let user_data = entities::user_info::Entity::find_by_id(user_id)
+        .filter(entities::user_info::Column::DeletedAt.is_null())
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User Not Found".to_owned(),
+        })?;
+
+    login_response(&app_state, &user_data, &req).await
+}
+
+/// Refresh token of a browser session, which must come with the matching CSRF header.
+fn cookie_refresh_token(req: &HttpRequest) -> Result<String, ServiceError> {
+    let refresh_cookie = req
+        .cookie(&constants::REFRESH_COOKIE_NAME)
+        .ok_or(ServiceError::Unauthorized {
+            error_message: "Missing refresh token".to_owned(),
+        })?;
+
+    if !auth_cookie::request_passes_csrf(req) {
+        return Err(ServiceError::Forbidden {
+            error_message: "Missing or invalid CSRF token".to_owned(),
+        });
+    }
+
+    Ok(refresh_cookie.value().to_owned())
+}
+
+#[post("/refresh")]
+pub async fn refresh(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    refresh_json: Option<web::Json<RefreshModel>>,
+) -> Result<ApiResponse<SessionModel>, ServiceError> {
+    let (presented_token, mode) = match refresh_json {
+        Some(refresh_json) => (
+            refresh_json.into_inner().refresh_token,
+            AuthMode::from_query(req.query_string()),
+        ),
+        None => (cookie_refresh_token(&req)?, AuthMode::Cookie),
+    };
+
+    let (refresh_token, refresh_model) =
+        refresh_token::rotate_refresh_token(&app_state.db, &presented_token)
+            .await
+            .map_err(|err| match err {
+                RefreshError::Db(err) => ServiceError::internal(err),
+                err => ServiceError::Unauthorized {
+                    error_message: err.to_string(),
+                },
+            })?;
+
+    let user_data = entities::user_info::Entity::find_by_id(refresh_model.user_id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::Unauthorized {
+            error_message: "Invalid refresh token".to_owned(),
+        })?;
+
+    check_account_enabled(&user_data)?;
+
+    // families issued before sessions were recorded have none
+    let user_session = session::touch_session(
+        &app_state.db,
+        &refresh_model.family_id,
+        &ClientInfo::from_request(&req),
+    )
+    .await?;
+
+    let token = encode_jwt(&user_data, user_session.map(|user_session| user_session.id))?;
+
+    token_response(token, refresh_token, mode)
+}
+
+/// Expires the session cookies when the request was made by a browser session.
+fn clear_auth_cookies(req: &HttpRequest, response: ApiResponse) -> ApiResponse {
+    if req.cookie(&constants::AUTH_COOKIE_NAME).is_none() {
+        return response;
+    }
+
+    auth_cookie::removal_cookies()
+        .into_iter()
+        .fold(response, |response, cookie| {
+            response.with_header("Set-Cookie", cookie.to_string())
+        })
+}
+
+#[post("/logout")]
+pub async fn logout(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+    logout_json: Option<web::Json<LogoutModel>>,
+) -> Result<ApiResponse, ServiceError> {
+    // a key is not a login, revoking its `jti` would change nothing
+    if claims.is_user() && !claims.is_account_holder() {
+        return Err(ServiceError::Forbidden {
+            error_message: "API keys are revoked through secure/user/api-keys".to_owned(),
+        });
+    }
+
+    app_state
+        .revocations
+        .revoke(&app_state.db, &claims)
+        .await?;
+
+    if let Some(session_id) = claims.sid {
+        let user_session = session::find_active_session(&app_state.db, claims.id, session_id)
+            .await?;
+
+        session::end_sessions(&app_state.db, &app_state.revocations, user_session.as_slice())
+            .await?;
+    }
+
+    // also end the refresh token family of this login when the client hands it over
+    let refresh_token = logout_json
+        .and_then(|json| json.into_inner().refresh_token)
+        .or(req
+            .cookie(&constants::REFRESH_COOKIE_NAME)
+            .map(|cookie| cookie.value().to_owned()));
+
+    if let Some(refresh_token) = refresh_token {
+        let refresh_model = entities::refresh_token::Entity::find()
+            .filter(
+                entities::refresh_token::Column::TokenHash
+                    .eq(refresh_token::hash_refresh_token(&refresh_token)),
+            )
+            .one(&app_state.db)
+            .await?;
+
+        if let Some(refresh_model) = refresh_model.filter(|model| model.user_id == claims.id) {
+            refresh_token::revoke_family(&app_state.db, &refresh_model.family_id)
+                .await?;
+        }
+    }
+
+    Ok(clear_auth_cookies(
+        &req,
+        api_response::ApiResponse::new(200, "Logged out".to_owned()),
     ))
 }
 
+#[post("/logout-all")]
+pub async fn logout_all(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<ApiResponse, ServiceError> {
+    // an OAuth2 client ends its tokens through /auth/logout or by being revoked
+    if !claims.is_account_holder() {
+        return Err(ServiceError::Forbidden {
+            error_message: "Only users can log out from all devices".to_owned(),
+        });
+    }
+
+    session::end_all_logins(&app_state.db, &app_state.revocations, claims.id)
+        .await?;
+
+    Ok(clear_auth_cookies(
+        &req,
+        api_response::ApiResponse::new(200, "Logged out from all devices".to_owned()),
+    ))
+}
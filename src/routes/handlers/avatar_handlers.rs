@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use actix_multipart::form::bytes::Bytes;
+use actix_multipart::form::MultipartForm;
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use actix_web::{get, post, web, HttpResponse};
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, EntityTrait, IntoActiveModel, Set};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::error::ServiceError;
+use crate::utils::avatar::{self, AvatarError};
+use crate::utils::blob_store::{Blob, BlobError};
+use crate::utils::{api_response, app_state, constants, jwt::Claims, profile};
+
+#[derive(MultipartForm)]
+struct AvatarUploadModel {
+    file: Bytes,
+}
+
+#[derive(Serialize)]
+struct AvatarModel {
+    image: String,
+    thumbnails: BTreeMap<u32, String>,
+}
+
+fn avatar_url(user_id: i32, avatar_id: &str, size: u32) -> String {
+    format!("/{}", avatar::avatar_key(user_id, avatar_id, size))
+}
+
+/// Replaces the profile image with thumbnails of the uploaded picture.
+#[post("avatar")]
+pub async fn upload_avatar(
+    app_state: web::Data<app_state::AppState>,
+    form: MultipartForm<AvatarUploadModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<AvatarModel>, ServiceError> {
+    let data = form.into_inner().file.data;
+
+    if data.len() as u64 > *constants::MAX_FILE_SIZE {
+        return Err(ServiceError::PayloadTooLarge {
+            error_message: "File is too large".to_string(),
+        });
+    }
+
+    avatar::sniff_format(&data).map_err(|err| ServiceError::UnsupportedMediaType {
+        error_message: err.to_string(),
+        accepted: None,
+    })?;
+
+    // decoding and resizing is CPU heavy, keep it off the async workers
+    let thumbnails = web::block(move || avatar::thumbnails(&data))
+        .await?
+        .map_err(|err| match err {
+            AvatarError::UnsupportedType => ServiceError::UnsupportedMediaType {
+                error_message: err.to_string(),
+                accepted: None,
+            },
+            AvatarError::Invalid(_) => ServiceError::BadRequest {
+                error_message: err.to_string(),
+            },
+        })?;
+
+    let user = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    let avatar_id = Uuid::new_v4().simple().to_string();
+    for (size, png) in thumbnails {
+        app_state
+            .blobs
+            .put(
+                &avatar::avatar_key(user.id, &avatar_id, size),
+                Blob {
+                    content_type: "image/png".to_string(),
+                    data: png,
+                },
+            )
+            .await
+            .map_err(ServiceError::internal)?;
+    }
+
+    let previous_avatar = avatar::avatar_id_from_url(user.id, &user.image);
+    let image = avatar_url(user.id, &avatar_id, avatar::AVATAR_SIZES[0]);
+
+    let updated_at = profile::next_updated_at(user.updated_at, Utc::now());
+    let mut user_model = user.into_active_model();
+    user_model.image = Set(image.clone());
+    user_model.updated_at = Set(updated_at);
+    let user = user_model
+        .update(&app_state.db)
+        .await?;
+
+    // the old thumbnails are unreachable now, failing to remove them only wastes space
+    if let Some(previous_avatar) = previous_avatar {
+        for size in avatar::AVATAR_SIZES {
+            if let Err(err) = app_state
+                .blobs
+                .delete(&avatar::avatar_key(user.id, &previous_avatar, size))
+                .await
+            {
+                log::warn!("could not delete old avatar of user {}: {}", user.id, err);
+            }
+        }
+    }
+
+    Ok(api_response::ApiResponse::ok(AvatarModel {
+        image,
+        thumbnails: avatar::AVATAR_SIZES
+            .iter()
+            .map(|size| (*size, avatar_url(user.id, &avatar_id, *size)))
+            .collect(),
+    }))
+}
+
+#[get("{user_id}/{avatar_id}/{file_name}")]
+pub async fn get_avatar(
+    app_state: web::Data<app_state::AppState>,
+    path: web::Path<(i32, String, String)>,
+) -> Result<HttpResponse, ServiceError> {
+    let (user_id, avatar_id, file_name) = path.into_inner();
+
+    let blob = app_state
+        .blobs
+        .get(&format!("avatars/{}/{}/{}", user_id, avatar_id, file_name))
+        .await
+        .map_err(|err| match err {
+            BlobError::NotFound | BlobError::InvalidKey(_) => {
+                ServiceError::NotFound {
+                    error_message: "Avatar not found".to_string(),
+                }
+            }
+            err => ServiceError::internal(err),
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, blob.content_type))
+        // every upload gets a new key, so a stored thumbnail never changes
+        .insert_header((CACHE_CONTROL, "public, max-age=31536000, immutable"))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .body(blob.data))
+}
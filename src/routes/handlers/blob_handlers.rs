@@ -0,0 +1,51 @@
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use actix_web::{get, web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::error::ServiceError;
+use crate::utils::app_state;
+use crate::utils::blob_store::BlobError;
+
+#[derive(Deserialize)]
+struct SignedUrlQuery {
+    expires: i64,
+    signature: String,
+}
+
+/// Serves a blob through a link made by `DownloadSigner::url`.
+#[get("{key:.*}")]
+pub async fn download_blob(
+    app_state: web::Data<app_state::AppState>,
+    key: web::Path<String>,
+    query: web::Query<SignedUrlQuery>,
+) -> Result<HttpResponse, ServiceError> {
+    if !app_state
+        .download_links
+        .verify(&key, query.expires, &query.signature, Utc::now())
+    {
+        return Err(ServiceError::Forbidden {
+            error_message: "Invalid or expired download link".to_string(),
+        });
+    }
+
+    let blob = app_state
+        .blobs
+        .get(&key)
+        .await
+        .map_err(|err| match err {
+            BlobError::NotFound | BlobError::InvalidKey(_) => {
+                ServiceError::NotFound {
+                    error_message: "File not found".to_string(),
+                }
+            }
+            err => ServiceError::internal(err),
+        })?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, blob.content_type))
+        // the link is a bearer credential, keep it out of shared caches
+        .insert_header((CACHE_CONTROL, "private, no-store"))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .body(blob.data))
+}
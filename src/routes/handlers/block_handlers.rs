@@ -1,22 +1,46 @@
-use crate::utils::{api_response, app_state};
-use actix_multipart::form::text::Text;
-use actix_multipart::form::MultipartForm;
+use crate::error::ServiceError;
+use crate::utils::request_body::{FromTextFields, TextFields, ValidatedBody};
+use crate::utils::{api_response, app_state, validation};
 use actix_web::{get, post, web};
 use chrono::{NaiveDateTime, Utc};
 use sea_orm::ActiveModelTrait;
-use sea_orm::{EntityTrait, Set, TransactionTrait};
+use sea_orm::{EntityTrait, Set};
 use serde::{Deserialize, Serialize};
+use validator::Validate;
 
-#[derive(MultipartForm)]
+#[derive(Deserialize, Validate)]
 struct CreateBlockModel {
-    chain_id: Text<String>,
-    block_number: Text<String>,
-    block_slot: Text<String>,
-    block_time: Text<String>,
-    block_hash: Text<String>,
-    block_parent_hash: Text<String>,
-    block_nonce: Text<String>,
-    block_difficulty: Text<String>,
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    chain_id: String,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    block_number: i32,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    block_slot: i32,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    block_time: i32,
+    #[validate(custom(function = "validation::hex_hash"))]
+    block_hash: String,
+    #[validate(custom(function = "validation::hex_hash"))]
+    block_parent_hash: String,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    block_nonce: i32,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    block_difficulty: i32,
+}
+
+impl FromTextFields for CreateBlockModel {
+    fn from_text_fields(fields: &mut TextFields) -> Self {
+        CreateBlockModel {
+            chain_id: fields.text("chain_id"),
+            block_number: fields.parse("block_number"),
+            block_slot: fields.parse("block_slot"),
+            block_time: fields.parse("block_time"),
+            block_hash: fields.text("block_hash"),
+            block_parent_hash: fields.text("block_parent_hash"),
+            block_nonce: fields.parse("block_nonce"),
+            block_difficulty: fields.parse("block_difficulty"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,104 +58,82 @@ struct BlockModel {
     pub user_id: i32,
 }
 
+impl From<entities::block_info::Model> for BlockModel {
+    fn from(block: entities::block_info::Model) -> Self {
+        BlockModel {
+            id: block.id,
+            block_hash: block.block_hash,
+            block_number: block.block_number.to_string(),
+            block_slot: block.block_slot.to_string(),
+            block_time: block.block_time.to_string(),
+            block_parent_hash: block.parent_hash,
+            block_nonce: block.nonce.to_string(),
+            block_difficulty: block.difficulty.to_string(),
+            block_gas_limit: String::new(),
+            created_at: block.created_at,
+            user_id: 0,
+        }
+    }
+}
+
+/// Takes JSON, urlencoded or multipart bodies, see `ValidatedBody`.
 #[post("create-block")]
 pub async fn create_block(
     app_state: web::Data<app_state::AppState>,
-    block_info: MultipartForm<CreateBlockModel>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
-    let txn = app_state
-        .db
-        .begin()
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+    block_info: ValidatedBody<CreateBlockModel>,
+) -> Result<api_response::ApiResponse<BlockModel>, ServiceError> {
+    let block_info = block_info.into_inner();
 
-    let post_entity = entities::block_info::ActiveModel {
-        chain_id: Set(block_info.chain_id.clone()),
-        block_number: Set(block_info.block_number.clone().parse::<i32>().unwrap()),
-        block_slot: Set(block_info.block_slot.clone().parse::<i32>().unwrap()),
-        block_time: Set(block_info.block_time.clone().parse::<i32>().unwrap()),
-        block_hash: Set(block_info.block_hash.clone()),
-        parent_hash: Set(block_info.block_parent_hash.clone()),
-        nonce: Set(block_info.block_nonce.clone().parse::<i32>().unwrap()),
-        difficulty: Set(block_info.block_difficulty.clone().parse::<i32>().unwrap()),
+    let block = entities::block_info::ActiveModel {
+        chain_id: Set(block_info.chain_id),
+        block_number: Set(block_info.block_number),
+        block_slot: Set(block_info.block_slot),
+        block_time: Set(block_info.block_time),
+        block_hash: Set(block_info.block_hash),
+        block_address: Set(String::new()),
+        block_memo: Set(String::new()),
+        parent_hash: Set(block_info.block_parent_hash),
+        nonce: Set(block_info.block_nonce),
+        difficulty: Set(block_info.block_difficulty),
         created_at: Set(Utc::now().naive_local()),
         updated_at: Set(Utc::now().naive_local()),
         ..Default::default()
-    };
+    }
+    .insert(&app_state.db)
+    .await?;
 
-    post_entity
-        .save(&txn)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+    let location = format!("/block/block/{}", block.id);
 
-    txn.commit()
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
-
-    Ok(api_response::ApiResponse::new(200, "OKKK".to_owned()))
+    Ok(api_response::ApiResponse::created(BlockModel::from(block)).with_header("Location", location))
 }
 
 #[get("block/{block_id}")]
 pub async fn one_block(
     app_state: web::Data<app_state::AppState>,
     block_id: web::Path<i32>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse<BlockModel>, ServiceError> {
     let block_info: BlockModel = entities::block_info::Entity::find_by_id(block_id.into_inner())
         .one(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
-        .map(|block| BlockModel {
-            id: block.id,
-            block_hash: block.block_hash.clone(),
-            block_number: block.block_number.to_string(),
-            block_slot: block.block_slot.to_string(),
-            block_time: block.block_time.to_string(),
-            block_parent_hash: block.block_hash.clone(),
-            block_nonce: block.block_hash.clone(),
-            block_difficulty: block.block_hash.clone(),
-            block_gas_limit: block.block_hash.clone(),
-            created_at: block.created_at,
-            user_id: 0,
-        })
-        .ok_or(api_response::ApiResponse::new(
-            404,
-            "Block not found".to_string(),
-        ))?;
+        .await?
+        .map(BlockModel::from)
+        .ok_or(ServiceError::NotFound {
+            error_message: "Block not found".to_string(),
+        })?;
 
-    let resp_str = serde_json::to_string(&block_info)
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
-
-    Ok(api_response::ApiResponse::new(200, resp_str.to_string()))
+    Ok(api_response::ApiResponse::ok(block_info))
 }
 
 #[get("all-blocks")]
 pub async fn all_blocks(
     app_state: web::Data<app_state::AppState>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse<Vec<BlockModel>>, ServiceError> {
     let all_blocks = entities::block_info::Entity::find()
         .all(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
-        .into_iter()    
-        .map(|blocks| {
-            BlockModel {
-                id: blocks.id,
-                block_hash: blocks.block_hash.clone(),
-                block_number: blocks.block_number.to_string(),
-                block_slot: blocks.block_slot.to_string(),
-                block_time: blocks.block_time.to_string(),
-                block_parent_hash: blocks.block_hash.clone(),
-                block_nonce: blocks.block_hash.clone(),
-                block_difficulty: blocks.block_hash.clone(),
-                block_gas_limit: blocks.block_hash.clone(),
-                created_at: blocks.created_at,
-                user_id: 0,
-            }
-        }).collect::<Vec<BlockModel>>();
-
-    let resp_str = serde_json::to_string(&all_blocks)
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+        .await?
+        .into_iter()
+        .map(BlockModel::from)
+        .collect::<Vec<BlockModel>>();
 
-    Ok(api_response::ApiResponse::new(200, resp_str.to_owned()))
+    Ok(api_response::ApiResponse::ok(all_blocks))
 }
 
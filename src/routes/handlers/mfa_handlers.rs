@@ -0,0 +1,176 @@
+use actix_web::{delete, post, web, HttpRequest};
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServiceError;
+use crate::utils::{api_response, app_state, jwt::Claims, login_throttle, mfa};
+
+#[derive(Serialize, Deserialize)]
+struct CodeModel {
+    code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EnrollmentModel {
+    secret: String,
+    otpauth_uri: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecoveryCodesModel {
+    recovery_codes: Vec<String>,
+}
+
+#[post("mfa/totp")]
+pub async fn enroll_totp(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<EnrollmentModel>, ServiceError> {
+    let user_mfa = entities::user_mfa::Entity::find()
+        .filter(entities::user_mfa::Column::UserId.eq(claims.id))
+        .one(&app_state.db)
+        .await?;
+
+    if user_mfa.as_ref().is_some_and(|mfa| mfa.enabled_at.is_some()) {
+        return Err(ServiceError::Conflict {
+            error_message: "MFA is already enabled".to_string(),
+        });
+    }
+
+    let secret = mfa::generate_secret();
+    let totp = mfa::totp(&secret, &claims.email)
+        .map_err(ServiceError::internal)?;
+
+    // restarting an unconfirmed enrollment replaces its secret
+    match user_mfa {
+        Some(user_mfa) => {
+            let mut mfa_model: entities::user_mfa::ActiveModel = user_mfa.into();
+            mfa_model.secret = Set(app_state.mfa_secrets.seal(&secret));
+            mfa_model.last_used_step = Set(None);
+            mfa_model.update(&app_state.db).await
+        }
+        None => {
+            entities::user_mfa::ActiveModel {
+                user_id: Set(claims.id),
+                secret: Set(app_state.mfa_secrets.seal(&secret)),
+                enabled_at: Set(None),
+                last_used_step: Set(None),
+                created_at: Set(Utc::now().naive_utc()),
+                ..Default::default()
+            }
+            .insert(&app_state.db)
+            .await
+        }
+    }?;
+
+    Ok(api_response::ApiResponse::ok(EnrollmentModel {
+        secret,
+        otpauth_uri: totp.get_url(),
+    }))
+}
+
+/// Account of the caller, for throttling code guesses the way login does.
+async fn find_user(
+    app_state: &app_state::AppState,
+    user_id: i32,
+) -> Result<entities::user_info::Model, ServiceError> {
+    entities::user_info::Entity::find_by_id(user_id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User Not Found".to_string(),
+        })
+}
+
+#[post("mfa/totp/confirm")]
+pub async fn confirm_totp(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    code_data: web::Json<CodeModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<RecoveryCodesModel>, ServiceError> {
+    let client_ip = req.peer_addr().map(|addr| addr.ip());
+    let user_data = find_user(&app_state, claims.id).await?;
+    login_throttle::check_attempt(&app_state, client_ip, Some(&user_data))?;
+
+    let user_mfa = entities::user_mfa::Entity::find()
+        .filter(entities::user_mfa::Column::UserId.eq(claims.id))
+        .filter(entities::user_mfa::Column::EnabledAt.is_null())
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "No pending MFA enrollment".to_string(),
+        })?;
+
+    let secret = app_state
+        .mfa_secrets
+        .open(&user_mfa.secret)
+        .map_err(ServiceError::internal)?;
+    let totp = mfa::totp(&secret, &claims.email)
+        .map_err(ServiceError::internal)?;
+
+    let Some(step) = mfa::matching_step(&totp, &code_data.code, Utc::now().timestamp() as u64) else {
+        login_throttle::record_failed_attempt(&app_state, client_ip, Some(&user_data)).await?;
+        return Err(ServiceError::BadRequest {
+            error_message: "Invalid code".to_string(),
+        });
+    };
+
+    login_throttle::reset_failed_attempts(&app_state, user_data).await?;
+
+    mfa::enable(&app_state.db, user_mfa, step)
+        .await?;
+
+    let recovery_codes = mfa::replace_recovery_codes(&app_state.db, claims.id)
+        .await?;
+
+    Ok(api_response::ApiResponse::ok(RecoveryCodesModel { recovery_codes }))
+}
+
+#[delete("mfa/totp")]
+pub async fn disable_totp(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    code_data: web::Json<CodeModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let client_ip = req.peer_addr().map(|addr| addr.ip());
+    let user_data = find_user(&app_state, claims.id).await?;
+    login_throttle::check_attempt(&app_state, client_ip, Some(&user_data))?;
+
+    let user_mfa = mfa::find_enabled(&app_state.db, claims.id)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "MFA is not enabled".to_string(),
+        })?;
+
+    let code_valid = mfa::verify_second_factor(
+        &app_state.db,
+        &app_state.mfa_secrets,
+        &user_mfa,
+        &claims.email,
+        &code_data.code,
+    )
+    .await?;
+
+    if !code_valid {
+        login_throttle::record_failed_attempt(&app_state, client_ip, Some(&user_data)).await?;
+        return Err(ServiceError::BadRequest {
+            error_message: "Invalid code".to_string(),
+        });
+    }
+
+    login_throttle::reset_failed_attempts(&app_state, user_data).await?;
+
+    entities::user_mfa::Entity::delete_by_id(user_mfa.id)
+        .exec(&app_state.db)
+        .await?;
+
+    entities::mfa_recovery_code::Entity::delete_many()
+        .filter(entities::mfa_recovery_code::Column::UserId.eq(claims.id))
+        .exec(&app_state.db)
+        .await?;
+
+    Ok(api_response::ApiResponse::new(200, "MFA disabled".to_string()))
+}
@@ -1,4 +1,14 @@
+pub mod account_handlers;
+pub mod admin_handlers;
+pub mod api_key_handlers;
+pub mod avatar_handlers;
+pub mod blob_handlers;
 pub mod block_handlers;
 pub mod auth_handlers;
+pub mod mfa_handlers;
+pub mod oauth_handlers;
+pub mod session_handlers;
 pub mod tx_handlers;
-pub mod user_handlers;
\ No newline at end of file
+pub mod user_handlers;
+pub mod wallet_handlers;
+pub mod well_known_handlers;
\ No newline at end of file
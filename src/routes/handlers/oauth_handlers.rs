@@ -0,0 +1,261 @@
+use std::fmt::Display;
+
+use actix_web::http::header::{AUTHORIZATION, CACHE_CONTROL, PRAGMA, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{
+    delete, get, post, web, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError,
+};
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::{ActiveModelTrait, EntityTrait, QueryOrder, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServiceError;
+use crate::utils::api_response::ApiResponse;
+use crate::utils::roles::Permission;
+use crate::utils::{api_key, app_state, constants, jwt, oauth_client};
+
+#[derive(Deserialize)]
+struct TokenRequestModel {
+    grant_type: Option<String>,
+    scope: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TokenResponseModel {
+    access_token: String,
+    token_type: String,
+    expires_in: i64,
+    scope: String,
+}
+
+/// Error body of the token endpoint, RFC 6749 section 5.2.
+#[derive(Serialize, Debug)]
+struct OAuthErrorModel {
+    error: &'static str,
+    error_description: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CreateOAuthClientModel {
+    name: String,
+    scopes: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OAuthClientModel {
+    pub client_id: String,
+    pub name: String,
+    pub scopes: Vec<Permission>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CreatedOAuthClientModel {
+    pub client_secret: String,
+    #[serde(flatten)]
+    pub client: OAuthClientModel,
+}
+
+impl From<entities::oauth_client::Model> for OAuthClientModel {
+    fn from(client: entities::oauth_client::Model) -> Self {
+        OAuthClientModel {
+            client_id: client.client_id,
+            name: client.name,
+            scopes: api_key::parse_scopes(&client.scopes).unwrap_or_default(),
+            revoked_at: client.revoked_at,
+            created_at: client.created_at,
+        }
+    }
+}
+
+// token responses must never be cached, RFC 6749 section 5.1
+fn token_endpoint_response(status_code: StatusCode) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::build(status_code);
+    builder
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header((PRAGMA, "no-cache"));
+    builder
+}
+
+/// Error of the token endpoint. OAuth clients expect the RFC 6749 body
+/// there, not the API envelope.
+#[derive(Debug)]
+struct OAuthError {
+    status_code: StatusCode,
+    body: OAuthErrorModel,
+}
+
+impl Display for OAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.body.error, self.body.error_description)
+    }
+}
+
+impl ResponseError for OAuthError {
+    fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut builder = token_endpoint_response(self.status_code);
+        if self.status_code == StatusCode::UNAUTHORIZED {
+            builder.insert_header((WWW_AUTHENTICATE, "Basic realm=\"oauth\""));
+        }
+
+        builder.json(&self.body)
+    }
+}
+
+fn oauth_error(status_code: u16, error: &'static str, description: &str) -> OAuthError {
+    OAuthError {
+        status_code: StatusCode::from_u16(status_code).unwrap(),
+        body: OAuthErrorModel {
+            error,
+            error_description: description.to_owned(),
+        },
+    }
+}
+
+/// Client credentials from the Basic header or the form body, never both.
+fn client_credentials(
+    req: &HttpRequest,
+    request_data: &TokenRequestModel,
+) -> Result<(String, String), OAuthError> {
+    let header = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let from_body = request_data.client_id.is_some() || request_data.client_secret.is_some();
+
+    match (header, from_body) {
+        (Some(_), true) => Err(oauth_error(
+            400,
+            "invalid_request",
+            "Use only one client authentication method",
+        )),
+        (Some(header), false) => oauth_client::basic_credentials(header)
+            .ok_or(oauth_error(401, "invalid_client", "Malformed Basic credentials")),
+        (None, _) => match (&request_data.client_id, &request_data.client_secret) {
+            (Some(client_id), Some(client_secret)) => Ok((client_id.clone(), client_secret.clone())),
+            _ => Err(oauth_error(401, "invalid_client", "Client authentication required")),
+        },
+    }
+}
+
+/// OAuth2 token endpoint. Only the `client_credentials` grant is supported.
+#[post("/token")]
+pub async fn token(
+    app_state: web::Data<app_state::AppState>,
+    req: HttpRequest,
+    request_data: Option<web::Form<TokenRequestModel>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let request_data = request_data.ok_or(oauth_error(
+        400,
+        "invalid_request",
+        "Expected an application/x-www-form-urlencoded body",
+    ))?;
+
+    match request_data.grant_type.as_deref() {
+        Some("client_credentials") => {}
+        Some(_) => {
+            return Err(oauth_error(
+                400,
+                "unsupported_grant_type",
+                "Only client_credentials is supported",
+            )
+            .into())
+        }
+        None => return Err(oauth_error(400, "invalid_request", "Missing grant_type").into()),
+    }
+
+    let (client_id, client_secret) = client_credentials(&req, &request_data)?;
+
+    let client = oauth_client::authenticate_client(&app_state.db, &client_id, &client_secret)
+        .await
+        .map_err(ServiceError::internal)?
+        .ok_or(oauth_error(401, "invalid_client", "Client authentication failed"))?;
+
+    let allowed = api_key::parse_scopes(&client.scopes).unwrap_or_default();
+    let granted = oauth_client::grant_scopes(&allowed, request_data.scope.as_deref()).ok_or(
+        oauth_error(400, "invalid_scope", "Requested scope is not allowed for this client"),
+    )?;
+
+    let scope = oauth_client::format_scope(&granted);
+    let access_token = jwt::encode_client_jwt(&client, granted)
+        .map_err(ServiceError::internal)?;
+
+    Ok(token_endpoint_response(StatusCode::OK).json(TokenResponseModel {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: *constants::ACCESS_TOKEN_TTL_MINUTES * 60,
+        scope,
+    }))
+}
+
+#[post("oauth-clients")]
+pub async fn create_client(
+    app_state: web::Data<app_state::AppState>,
+    client_data: web::Json<CreateOAuthClientModel>,
+) -> Result<ApiResponse<CreatedOAuthClientModel>, ServiceError> {
+    if client_data.name.trim().is_empty() || client_data.name.len() > 100 {
+        return Err(ServiceError::BadRequest {
+            error_message: "Name must be between 1 and 100 characters".to_string(),
+        });
+    }
+
+    let generated = oauth_client::generate_client_credentials();
+
+    let client_model = entities::oauth_client::ActiveModel {
+        client_id: Set(generated.client_id),
+        name: Set(client_data.name.trim().to_owned()),
+        secret_hash: Set(generated.secret_hash),
+        scopes: Set(api_key::format_scopes(&client_data.scopes)),
+        revoked_at: Set(None),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    }
+    .insert(&app_state.db)
+    .await?;
+
+    Ok(ApiResponse::created(CreatedOAuthClientModel {
+        client_secret: generated.client_secret,
+        client: client_model.into(),
+    }))
+}
+
+#[get("oauth-clients")]
+pub async fn list_clients(
+    app_state: web::Data<app_state::AppState>,
+) -> Result<ApiResponse<Vec<OAuthClientModel>>, ServiceError> {
+    let clients = entities::oauth_client::Entity::find()
+        .order_by_desc(entities::oauth_client::Column::CreatedAt)
+        .all(&app_state.db)
+        .await?
+        .into_iter()
+        .map(OAuthClientModel::from)
+        .collect::<Vec<OAuthClientModel>>();
+
+    Ok(ApiResponse::ok(clients))
+}
+
+#[delete("oauth-clients/{client_id}")]
+pub async fn revoke_client(
+    app_state: web::Data<app_state::AppState>,
+    client_id: web::Path<String>,
+) -> Result<ApiResponse, ServiceError> {
+    let revoked = app_state
+        .revocations
+        .revoke_client(&app_state.db, &client_id)
+        .await?;
+
+    if !revoked {
+        return Err(ServiceError::NotFound {
+            error_message: "OAuth client not found".to_string(),
+        });
+    }
+
+    Ok(ApiResponse::new(200, "OAuth client revoked".to_string()))
+}
@@ -0,0 +1,77 @@
+use actix_web::{delete, get, web};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServiceError;
+use crate::utils::{api_response, app_state, jwt::Claims, session};
+
+#[derive(Serialize, Deserialize)]
+struct SessionModel {
+    pub id: i32,
+    pub user_agent: String,
+    pub ip_address: String,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    /// The session the request was made with.
+    pub current: bool,
+}
+
+#[get("sessions")]
+pub async fn list_sessions(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<Vec<SessionModel>>, ServiceError> {
+    let sessions = session::active_sessions(&app_state.db, claims.id)
+        .await?
+        .into_iter()
+        .map(|user_session| SessionModel {
+            current: claims.sid == Some(user_session.id),
+            id: user_session.id,
+            user_agent: user_session.user_agent,
+            ip_address: user_session.ip_address,
+            created_at: user_session.created_at,
+            last_seen_at: user_session.last_seen_at,
+        })
+        .collect::<Vec<SessionModel>>();
+
+    Ok(api_response::ApiResponse::ok(sessions))
+}
+
+#[delete("sessions/{session_id}")]
+pub async fn revoke_session(
+    app_state: web::Data<app_state::AppState>,
+    session_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let user_session = session::find_active_session(&app_state.db, claims.id, session_id.into_inner())
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "Session not found".to_string(),
+        })?;
+
+    session::end_sessions(&app_state.db, &app_state.revocations, &[user_session])
+        .await?;
+
+    Ok(api_response::ApiResponse::new(200, "Session revoked".to_string()))
+}
+
+/// Signs out everywhere except the session making the request.
+#[delete("sessions")]
+pub async fn revoke_other_sessions(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let others = session::active_sessions(&app_state.db, claims.id)
+        .await?
+        .into_iter()
+        .filter(|user_session| claims.sid != Some(user_session.id))
+        .collect::<Vec<_>>();
+
+    session::end_sessions(&app_state.db, &app_state.revocations, &others)
+        .await?;
+
+    Ok(api_response::ApiResponse::new(
+        200,
+        format!("{} sessions revoked", others.len()),
+    ))
+}
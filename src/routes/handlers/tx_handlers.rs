@@ -1,25 +1,54 @@
-use crate::utils::{api_response, app_state};
-use actix_multipart::form::text::Text;
-use actix_multipart::form::MultipartForm;
+use crate::error::ServiceError;
+use crate::utils::request_body::{FromTextFields, TextFields, ValidatedBody};
+use crate::utils::{api_response, app_state, validation, wallet};
 use actix_web::{get, post, web};
 use chrono::{NaiveDateTime, Utc};
 use sea_orm::{ActiveModelTrait, QueryFilter};
-use sea_orm::{EntityTrait, Set, TransactionTrait};
+use sea_orm::{EntityTrait, Set};
 use serde::{Deserialize, Serialize};
 use sea_orm::ColumnTrait;
+use validator::Validate;
 
 
-#[derive(MultipartForm)]
+#[derive(Deserialize, Validate)]
 struct CreateTxModel {
-    tx_type: Text<i32>,
-    tx_hash: Text<String>,
-    from_address: Text<String>,
-    to_address: Text<String>,
-    tx_memo: Text<String>,
-    tx_amount: Text<String>,
-    tx_fee: Text<String>,
-    tx_status: Text<String>,
-    tx_time: Text<String>,
+    #[validate(range(min = 1, message = "must be the id of a block"))]
+    block_id: i32,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    tx_type: i32,
+    #[validate(custom(function = "validation::hex_hash"))]
+    tx_hash: String,
+    #[validate(custom(function = "validation::chain_address"))]
+    from_address: String,
+    #[validate(custom(function = "validation::chain_address"))]
+    to_address: String,
+    #[validate(length(max = 1024, message = "must be at most 1024 characters"))]
+    tx_memo: String,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    tx_amount: i32,
+    #[validate(range(min = 0, message = "must not be negative"))]
+    tx_fee: i32,
+    #[validate(length(min = 1, max = 32, message = "must be between 1 and 32 characters"))]
+    tx_status: String,
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    tx_time: String,
+}
+
+impl FromTextFields for CreateTxModel {
+    fn from_text_fields(fields: &mut TextFields) -> Self {
+        CreateTxModel {
+            block_id: fields.parse("block_id"),
+            tx_type: fields.parse("tx_type"),
+            tx_hash: fields.text("tx_hash"),
+            from_address: fields.text("from_address"),
+            to_address: fields.text("to_address"),
+            tx_memo: fields.text("tx_memo"),
+            tx_amount: fields.parse("tx_amount"),
+            tx_fee: fields.parse("tx_fee"),
+            tx_status: fields.text("tx_status"),
+            tx_time: fields.text("tx_time"),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -39,198 +68,139 @@ struct TxModel {
     pub updated_at: NaiveDateTime,
 }
 
+impl From<entities::tx_info::Model> for TxModel {
+    fn from(tx: entities::tx_info::Model) -> Self {
+        TxModel {
+            id: tx.id,
+            block_id: tx.block_id,
+            tx_hash: tx.tx_hash,
+            tx_type: tx.tx_type,
+            from_address: tx.from_address,
+            to_address: tx.to_address,
+            tx_memo: tx.tx_memo,
+            tx_amount: tx.tx_amount.to_string(),
+            tx_fee: tx.tx_fee.to_string(),
+            tx_status: tx.tx_status,
+            tx_time: tx.tx_time,
+            created_at: tx.created_at,
+            updated_at: tx.updated_at,
+        }
+    }
+}
+
+/// Takes JSON, urlencoded or multipart bodies, see `ValidatedBody`.
 #[post("create-tx")]
 pub async fn create_tx(
     app_state: web::Data<app_state::AppState>,
-    tx_info: MultipartForm<CreateTxModel>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
-    let txn = app_state
-        .db
-        .begin()
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
-
-    let tx_entity = entities::tx_info::ActiveModel {
-        tx_type:   Set(tx_info.tx_type.clone()), 
-        from_address: Set(tx_info.from_address.clone()),
-        to_address: Set(tx_info.to_address.clone()),
-        tx_memo: Set(tx_info.tx_memo.clone()),
-        tx_amount: Set(tx_info.tx_amount.clone().parse::<i32>().unwrap()),
-        tx_fee: Set(tx_info.tx_fee.clone().parse::<i32>().unwrap()),
-        tx_status: Set(tx_info.tx_status.clone()),
-        tx_time: Set(tx_info.tx_time.clone()),
-        tx_hash: Set(tx_info.tx_hash.clone()),
+    tx_info: ValidatedBody<CreateTxModel>,
+) -> Result<api_response::ApiResponse<TxModel>, ServiceError> {
+    let tx_info = tx_info.into_inner();
+
+    let block = entities::block_info::Entity::find_by_id(tx_info.block_id)
+        .one(&app_state.db)
+        .await?;
+
+    if block.is_none() {
+        return Err(validation::failed(validation::FieldErrors::from([(
+            "block_id".to_owned(),
+            "does not exist".to_owned(),
+        )])));
+    }
+
+    let tx = entities::tx_info::ActiveModel {
+        block_id: Set(tx_info.block_id),
+        tx_type: Set(tx_info.tx_type),
+        from_address: Set(tx_info.from_address),
+        to_address: Set(tx_info.to_address),
+        tx_memo: Set(tx_info.tx_memo),
+        tx_amount: Set(tx_info.tx_amount),
+        tx_fee: Set(tx_info.tx_fee),
+        tx_status: Set(tx_info.tx_status),
+        tx_time: Set(tx_info.tx_time),
+        tx_hash: Set(tx_info.tx_hash),
         created_at: Set(Utc::now().naive_local()),
         updated_at: Set(Utc::now().naive_local()),
         ..Default::default()
-    };
-
-    tx_entity 
-        .save(&txn)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+    }
+    .insert(&app_state.db)
+    .await?;
 
-    txn.commit()
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+    let location = format!("/tx/tx/{}", tx.id);
 
-    Ok(api_response::ApiResponse::new(200, "Transaction created successfully".to_owned()))
+    Ok(api_response::ApiResponse::created(TxModel::from(tx)).with_header("Location", location))
 }
 
 #[get("tx/{tx_id}")]
 pub async fn one_tx(
     app_state: web::Data<app_state::AppState>,
     tx_id: web::Path<i32>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse<TxModel>, ServiceError> {
     let tx_info: TxModel = entities::tx_info::Entity::find_by_id(tx_id.into_inner())
         .one(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
-        .map(|tx| TxModel {
-            id: tx.id,
-            block_id: tx.block_id,
-            tx_hash: tx.tx_hash.clone(),
-            tx_type: tx.tx_type,
-            from_address: tx.from_address.clone(),
-            to_address: tx.to_address.clone(),
-            tx_memo: tx.tx_memo.clone(),
-            tx_amount: tx.tx_amount.to_string(),
-            tx_fee: tx.tx_fee.to_string(),
-            tx_status: tx.tx_status.clone(),
-            tx_time: tx.tx_time.to_string(),
-            created_at: tx.created_at,
-            updated_at: tx.updated_at,
-        })
-        .ok_or(api_response::ApiResponse::new(
-            404,
-            "Tx not found".to_string(),
-        ))?;
+        .await?
+        .map(TxModel::from)
+        .ok_or(ServiceError::NotFound {
+            error_message: "Tx not found".to_string(),
+        })?;
 
-    let resp_str = serde_json::to_string(&tx_info)
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
-
-    Ok(api_response::ApiResponse::new(200, resp_str.to_string()))
+    Ok(api_response::ApiResponse::ok(tx_info))
 }
 
 #[get("all-txs")]
 pub async fn all_txs(
     app_state: web::Data<app_state::AppState>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse<Vec<TxModel>>, ServiceError> {
     let all_txs = entities::tx_info::Entity::find()
         .all(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
-        .into_iter()    
-        .map(|txs| {
-            TxModel {
-                id: txs.id,
-                block_id: txs.block_id,
-                tx_hash: txs.tx_hash.clone(),
-                tx_type: txs.tx_type,
-                from_address: txs.from_address.clone(),
-                to_address: txs.to_address.clone(),
-                tx_memo: txs.tx_memo.clone(),
-                tx_amount: txs.tx_amount.to_string(),
-                tx_fee: txs.tx_fee.to_string(),
-                tx_status: txs.tx_status.clone(),
-                tx_time: txs.tx_time.to_string(),
-                created_at: txs.created_at,
-                updated_at: txs.updated_at,
-            }
-        }).collect::<Vec<TxModel>>();
-
-        let resp_str = serde_json::to_string(&all_txs)
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
-
-    Ok(api_response::ApiResponse::new(200, resp_str.to_owned()))
+        .await?
+        .into_iter()
+        .map(TxModel::from)
+        .collect::<Vec<TxModel>>();
+
+        Ok(api_response::ApiResponse::ok(all_txs))
 }
 
 #[get("tx-by-block-id/{block_id}")]
 pub async fn tx_by_block_id(
     app_state: web::Data<app_state::AppState>,
     block_id: web::Path<i32>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse<Vec<TxModel>>, ServiceError> {
     let txs = entities::tx_info::Entity::find()
         .filter(entities::tx_info::Column::BlockId.eq(block_id.into_inner()))
         .all(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
+        .await?
         .into_iter()
-        .map(|txs| {
-            TxModel {
-                id: txs.id,
-                block_id: txs.block_id,
-                tx_type: txs.tx_type,
-                tx_hash: txs.tx_hash.clone(),
-                from_address: txs.from_address.clone(),
-                to_address: txs.to_address.clone(),
-                tx_memo: txs.tx_memo.clone(),       
-                tx_amount: txs.tx_amount.to_string(),       
-                tx_fee: txs.tx_fee.to_string(),
-                tx_status: txs.tx_status.clone(),   
-                tx_time: txs.tx_time.to_string(),
-                created_at: txs.created_at,
-                updated_at: txs.updated_at,
-            }
-        }).collect::<Vec<TxModel>>();
-
-    let resp_str = serde_json::to_string(&txs)
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
-
-Ok(api_response::ApiResponse::new(200, resp_str.to_owned()))
+        .map(TxModel::from)
+        .collect::<Vec<TxModel>>();
+
+    Ok(api_response::ApiResponse::ok(txs))
 }
 
 #[get("tx-by-user-id/{user_id}")]
 pub async fn tx_by_user_id(
     app_state: web::Data<app_state::AppState>,
     user_id: web::Path<i32>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {         
+) -> Result<api_response::ApiResponse<Vec<TxModel>>, ServiceError> {         
 
-    // get user wallet address from user_id
+    let user_id = user_id.into_inner();
     let user_info = entities::user_info::Entity::find()
-        .filter(entities::user_info::Column::Id.eq(user_id.into_inner()))
+        .filter(entities::user_info::Column::Id.eq(user_id))
         .one(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+        .await?;
 
     if user_info.is_none() {
-        return Ok(api_response::ApiResponse::new(404, "User not found".to_string()));
+        return Err(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        });
     }
-    let user_info_val = user_info.unwrap();
-    let user_wallet_address = user_info_val.wallet_address;
 
-
-    // get txs from user wallet address
-    let txs = entities::tx_info::Entity::find()
-        .filter(
-            entities::tx_info::Column::FromAddress.eq(user_wallet_address.to_string())
-            .or(entities::tx_info::Column::ToAddress.eq(user_wallet_address.to_string()))
-        )
-        .all(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
+    // activity of every wallet the user proved to own
+    let txs = wallet::user_transactions(&app_state.db, user_id)
+        .await?
         .into_iter()
-        .map(|txs| {
-            TxModel {
-                id: txs.id, 
-                block_id: txs.block_id,
-                tx_type: txs.tx_type,
-                tx_hash: txs.tx_hash.clone(),
-                from_address: txs.from_address.clone(),
-                to_address: txs.to_address.clone(),
-                tx_memo: txs.tx_memo.clone(),
-                tx_amount: txs.tx_amount.to_string(),
-                tx_fee: txs.tx_fee.to_string(),
-                tx_status: txs.tx_status.clone(),
-                tx_time: txs.tx_time.to_string(),
-                created_at: txs.created_at,
-                updated_at: txs.updated_at,
-            }
-        }).collect::<Vec<TxModel>>();
-
-    let resp_str = serde_json::to_string(&txs)
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
-
-    Ok(api_response::ApiResponse::new(200, resp_str.to_owned()))
+        .map(TxModel::from)
+        .collect::<Vec<TxModel>>();
+
+    Ok(api_response::ApiResponse::ok(txs))
 }
 
@@ -1,25 +1,75 @@
-use actix_web::{get, post, web};
+use actix_web::http::header::{CONTENT_TYPE, IF_MATCH};
+use actix_web::{get, patch, post, web, HttpRequest};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::Expr;
 use sea_orm::{ActiveModelTrait, EntityTrait, IntoActiveModel, QueryFilter, Set};
 use sea_orm::{ColumnTrait, Condition};
 use serde::{Deserialize, Serialize};
-use sha256::digest;
+use validator::Validate;
 
-use crate::utils::{api_response, app_state, jwt::Claims};
+use super::account_handlers;
+use crate::error::{Accepted, ServiceError};
+use crate::utils::blob_store::Blob;
+use crate::utils::password::{self, PasswordCheck};
+use crate::utils::profile::{self, Profile};
+use crate::utils::{
+    api_response, app_state, jwt::Claims, personal_data, session, trusted_issuers, validation,
+};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Validate)]
 struct UpdateUserInfo {
+    #[validate(custom(function = "validation::name"))]
     name: String,
+    #[validate(custom(function = "validation::age"))]
     age: i32,
+    #[validate(custom(function = "validation::image"))]
     image: String,
 }
 
+#[derive(Serialize, Deserialize, Validate)]
+struct ChangePasswordModel {
+    current_password: String,
+    #[validate(custom(function = "validation::password"))]
+    new_password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DeleteAccountModel {
+    /// Required unless the account signs in through SSO only.
+    password: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
+struct DeletedAccountModel {
+    deleted_at: DateTime<Utc>,
+    purge_after: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportLinkModel {
+    url: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegisteredModel {
+    id: i32,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
 struct RegisterModel {
+    #[validate(custom(function = "validation::name"))]
     name: String,
+    #[validate(custom(function = "validation::email"))]
     email: String,
+    #[validate(custom(function = "validation::age"))]
     age: i32,
+    #[validate(custom(function = "validation::image"))]
     image: String,
+    #[validate(custom(function = "validation::password"))]
     password: String,
+    #[serde(default)]
+    #[validate(custom(function = "validation::optional_chain_address"))]
     wallet_address: String,
 }
 
@@ -27,23 +77,92 @@ struct RegisterModel {
 pub async fn my_info(
     app_state: web::Data<app_state::AppState>,
     claims: web::ReqData<Claims>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse<Profile>, ServiceError> {
     let user_model = entities::user_info::Entity::find_by_id(claims.id)
         .one(&app_state.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
-        .ok_or(api_response::ApiResponse::new(
-            404,
-            "User not found".to_string(),
-        ))?;
-
-    Ok(api_response::ApiResponse::new(
-        200,
-        format!(
-            " {{ 'name': '{}', 'age': '{}', 'image': '{}' }} ",
-            user_model.name, user_model.age, user_model.image
-        ),
-    ))
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    Ok(api_response::ApiResponse::ok(Profile::from(&user_model))
+        .with_header("ETag", profile::etag(user_model.updated_at)))
+}
+
+/// RFC 7396 merge patch of the profile, guarded by the ETag from `my_info`.
+#[patch("")]
+pub async fn patch_user_info(
+    req: HttpRequest,
+    app_state: web::Data<app_state::AppState>,
+    body: web::Bytes,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<Profile>, ServiceError> {
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if !content_type.starts_with(profile::MERGE_PATCH_CONTENT_TYPE) {
+        return Err(ServiceError::UnsupportedMediaType {
+            error_message: format!("Content-Type must be {}", profile::MERGE_PATCH_CONTENT_TYPE),
+            accepted: Some(Accepted::Patch(profile::MERGE_PATCH_CONTENT_TYPE.to_string())),
+        });
+    }
+
+    // without a precondition two clients would silently overwrite each other
+    let if_match = req
+        .headers()
+        .get(IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(ServiceError::PreconditionRequired {
+            error_message: "If-Match with the ETag of my_info is required".to_string(),
+        })?
+        .to_owned();
+
+    let patch = serde_json::from_slice::<serde_json::Value>(&body)
+        .map_err(|err| ServiceError::BadRequest {
+            error_message: err.to_string(),
+        })?;
+
+    let user = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    let current_etag = profile::etag(user.updated_at);
+    if !profile::if_match(&if_match, &current_etag) {
+        return Err(ServiceError::PreconditionFailed {
+            error_message: "Profile was changed by someone else".to_string(),
+            etag: Some(current_etag),
+        });
+    }
+
+    let patched = Profile::from(&user).patched(&patch)?;
+
+    let updated_at = profile::next_updated_at(user.updated_at, Utc::now());
+
+    // compare-and-set on updated_at, a concurrent write between read and update loses
+    let result = entities::user_info::Entity::update_many()
+        .col_expr(entities::user_info::Column::Name, Expr::value(patched.name.clone()))
+        .col_expr(entities::user_info::Column::Age, Expr::value(patched.age))
+        .col_expr(entities::user_info::Column::Image, Expr::value(patched.image.clone()))
+        .col_expr(entities::user_info::Column::UpdatedAt, Expr::value(updated_at))
+        .filter(entities::user_info::Column::Id.eq(user.id))
+        .filter(entities::user_info::Column::UpdatedAt.eq(user.updated_at))
+        .exec(&app_state.db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(ServiceError::PreconditionFailed {
+            error_message: "Profile was changed by someone else".to_string(),
+            etag: None,
+        });
+    }
+
+    Ok(api_response::ApiResponse::ok(patched).with_header("ETag", profile::etag(updated_at)))
 }
 
 #[post("update")]
@@ -51,65 +170,214 @@ pub async fn update_user_info(
     data: web::Data<app_state::AppState>,
     user_data: web::Json<UpdateUserInfo>,
     claims: web::ReqData<Claims>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse, ServiceError> {
+    validation::validate(&*user_data)?;
+
     let mut user_model = entities::user_info::Entity::find_by_id(claims.id)
         .one(&data.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?
-        .ok_or(api_response::ApiResponse::new(
-            404,
-            "User not found".to_string(),
-        ))?
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?
         .into_active_model();
 
-    user_model.name = Set(user_data.name.clone());
+    user_model.name = Set(user_data.name.trim().to_owned());
     user_model.age = Set(user_data.age);
     user_model.image = Set(user_data.image.clone());
+    user_model.updated_at = Set(profile::next_updated_at(*user_model.updated_at.as_ref(), Utc::now()));
 
     user_model
         .update(&data.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+        .await?;
+
+    Ok(api_response::ApiResponse::new(200, "success".to_string()))
+}
+
+#[post("change-password")]
+pub async fn change_password(
+    data: web::Data<app_state::AppState>,
+    password_data: web::Json<ChangePasswordModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    validation::validate(&*password_data)?;
+
+    let user = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&data.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    let current_password = password_data.current_password.clone();
+    let stored_hash = user.password.clone();
+    let password_check =
+        web::block(move || password::verify_password(&current_password, &stored_hash))
+            .await?;
+
+    if password_check == PasswordCheck::Invalid {
+        return Err(ServiceError::Unauthorized {
+            error_message: "Current password is incorrect".to_string(),
+        });
+    }
+
+    let new_password = password_data.new_password.clone();
+    let new_hash = web::block(move || password::hash_password(&new_password))
+        .await?
+        .map_err(ServiceError::internal)?;
+
+    let mut user_model = user.into_active_model();
+    user_model.password = Set(new_hash);
+    user_model
+        .update(&data.db)
+        .await?;
+
+    // a new password ends every existing login
+    session::end_all_logins(&data.db, &data.revocations, claims.id)
+        .await?;
 
     Ok(api_response::ApiResponse::new(200, "success".to_string()))
 }
 
+#[get("export")]
+pub async fn export_account(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<personal_data::AccountExport>, ServiceError> {
+    let user = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    let export = personal_data::export_account(&app_state.db, &user)
+        .await?;
+
+    Ok(api_response::ApiResponse::ok(export)
+        .with_header(
+            "Content-Disposition",
+            format!("attachment; filename=\"account-{}.json\"", user.id),
+        )
+        .with_header("Cache-Control", "no-store".to_string()))
+}
+
+/// Stores the export and returns a short-lived link to it, for clients that
+/// hand the download to a browser and cannot attach the access token.
+#[post("export/link")]
+pub async fn export_account_link(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<ExportLinkModel>, ServiceError> {
+    let user = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    let export = personal_data::export_account(&app_state.db, &user)
+        .await?;
+    let data = serde_json::to_vec(&export).map_err(ServiceError::internal)?;
+
+    let key = personal_data::export_key(user.id);
+    app_state
+        .blobs
+        .put(&key, Blob {
+            content_type: "application/json".to_string(),
+            data,
+        })
+        .await
+        .map_err(ServiceError::internal)?;
+
+    let expires_at = Utc::now() + Duration::minutes(personal_data::EXPORT_LINK_TTL_MINUTES);
+    Ok(api_response::ApiResponse::ok(ExportLinkModel {
+        url: app_state.download_links.url(&key, expires_at),
+        expires_at,
+    })
+    .with_header("Cache-Control", "no-store".to_string()))
+}
+
+/// Deletes the account after `ACCOUNT_DELETION_GRACE_DAYS`; until then it is only disabled.
+#[post("delete")]
+pub async fn delete_account(
+    app_state: web::Data<app_state::AppState>,
+    delete_data: web::Json<DeleteAccountModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<DeletedAccountModel>, ServiceError> {
+    let user = entities::user_info::Entity::find_by_id(claims.id)
+        .one(&app_state.db)
+        .await?
+        .ok_or(ServiceError::NotFound {
+            error_message: "User not found".to_string(),
+        })?;
+
+    // a stolen access token alone must not be enough to destroy the account
+    if !trusted_issuers::is_sso_only(&app_state.db, &user).await? {
+        let current_password = delete_data.password.clone().unwrap_or_default();
+        let stored_hash = user.password.clone();
+        let password_check =
+            web::block(move || password::verify_password(&current_password, &stored_hash))
+                .await?;
+
+        if password_check == PasswordCheck::Invalid {
+            return Err(ServiceError::Unauthorized {
+                error_message: "Current password is incorrect".to_string(),
+            });
+        }
+    }
+
+    let user = personal_data::soft_delete(&app_state.db, &app_state.revocations, user)
+        .await?;
+
+    let deleted_at = user.deleted_at.unwrap_or_else(Utc::now);
+    Ok(api_response::ApiResponse::with_data(202, DeletedAccountModel {
+        deleted_at,
+        purge_after: personal_data::purge_after(deleted_at),
+    }))
+}
+
 #[post("register")]
 pub async fn register(
     data: web::Data<app_state::AppState>,
     user_data: web::Json<RegisterModel>,
-) -> Result<api_response::ApiResponse, api_response::ApiResponse> {
+) -> Result<api_response::ApiResponse<RegisteredModel>, ServiceError> {
+    validation::validate(&*user_data)?;
+
     // check if user already exists
     let user_exists = entities::user_info::Entity::find()
         .filter(Condition::all().add(entities::user_info::Column::Email.eq(&user_data.email)))
         .one(&data.db)
-        .await
-        .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+        .await?;
 
     if user_exists.is_some() {
-        return Ok(api_response::ApiResponse::new(
-            400,
-            "User already exists".to_string(),
-        ));
+        return Err(ServiceError::BadRequest {
+            error_message: "User already exists".to_string(),
+        });
     }
 
+    let password = user_data.password.clone();
+    let password_hash = web::block(move || password::hash_password(&password))
+        .await?
+        .map_err(ServiceError::internal)?;
+
     // create user
     let user_model = entities::user_info::ActiveModel {
-        name: Set(user_data.name.clone()),
+        name: Set(user_data.name.trim().to_owned()),
         age: Set(user_data.age),
         image: Set(user_data.image.clone()),
         email: Set(user_data.email.clone()),
-        password: Set(digest(&user_data.password)),
+        password: Set(password_hash),
         wallet_address: Set(user_data.wallet_address.clone()),
         ..Default::default()
     }
     .insert(&data.db)
-    .await
-    .map_err(|err| api_response::ApiResponse::new(500, err.to_string()))?;
+    .await?;
+
+    // the account exists either way, the user can ask for another mail later
+    if let Err(err) = account_handlers::send_verification_email(&data, &user_model).await {
+        log::error!("verification mail for user {} failed: {}", user_model.id, err);
+    }
 
-    Ok(api_response::ApiResponse::new(
-        200,
-        format!("{}", user_model.id),
-    ))
+    Ok(api_response::ApiResponse::ok(RegisteredModel { id: user_model.id }))
 }
 
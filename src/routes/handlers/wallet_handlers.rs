@@ -0,0 +1,188 @@
+use actix_web::{delete, get, post, web};
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+
+use crate::error::ServiceError;
+use crate::utils::siwe::SiweMessage;
+use crate::utils::{api_response, app_state, constants, eth, jwt::Claims, nonce, wallet};
+
+#[derive(Serialize, Deserialize)]
+struct WalletChallengeRequestModel {
+    address: String,
+    chain_id: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WalletChallengeModel {
+    /// Sign this exact text with `personal_sign`.
+    message: String,
+    expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AddWalletModel {
+    message: String,
+    signature: String,
+    #[serde(default)]
+    label: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WalletModel {
+    pub id: i32,
+    pub chain_id: i64,
+    /// EIP-55 checksummed.
+    pub address: String,
+    pub label: String,
+    pub verified_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<entities::user_wallet::Model> for WalletModel {
+    fn from(wallet: entities::user_wallet::Model) -> Self {
+        WalletModel {
+            id: wallet.id,
+            chain_id: wallet.chain_id,
+            address: eth::to_checksum_address(&wallet.address).unwrap_or(wallet.address),
+            label: wallet.label,
+            verified_at: wallet.verified_at,
+            created_at: wallet.created_at,
+        }
+    }
+}
+
+#[post("wallets/challenge")]
+pub async fn wallet_challenge(
+    app_state: web::Data<app_state::AppState>,
+    challenge_data: web::Json<WalletChallengeRequestModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<WalletChallengeModel>, ServiceError> {
+    let address = eth::normalize_address(&challenge_data.address).ok_or(
+        ServiceError::BadRequest {
+            error_message: "Invalid wallet address".to_string(),
+        },
+    )?;
+
+    let (nonce, expires_at) = nonce::issue_nonce(&app_state.db)
+        .await?;
+
+    let challenge = wallet::link_challenge(claims.id, &address, challenge_data.chain_id, nonce, expires_at);
+
+    Ok(api_response::ApiResponse::ok(WalletChallengeModel {
+        message: challenge.to_string(),
+        expires_at,
+    }))
+}
+
+#[post("wallets")]
+pub async fn add_wallet(
+    app_state: web::Data<app_state::AppState>,
+    wallet_data: web::Json<AddWalletModel>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<WalletModel>, ServiceError> {
+    if wallet_data.label.len() > 100 {
+        return Err(ServiceError::BadRequest {
+            error_message: "Label must be at most 100 characters".to_string(),
+        });
+    }
+
+    let message = wallet_data
+        .message
+        .parse::<SiweMessage>()
+        .map_err(|err| ServiceError::BadRequest {
+            error_message: err.to_string(),
+        })?;
+
+    if message.domain != *constants::SIWE_DOMAIN
+        || message.statement != Some(wallet::link_statement(claims.id))
+    {
+        return Err(ServiceError::BadRequest {
+            error_message: "Not a wallet challenge for this account".to_string(),
+        });
+    }
+
+    let signature_valid = message
+        .verify(&wallet_data.message, &wallet_data.signature, Utc::now())
+        .map_err(|err| ServiceError::BadRequest {
+            error_message: err.to_string(),
+        })?;
+
+    if !signature_valid {
+        return Err(ServiceError::BadRequest {
+            error_message: "Invalid signature".to_string(),
+        });
+    }
+
+    let nonce_valid = nonce::consume_nonce(&app_state.db, &message.nonce)
+        .await?;
+
+    if !nonce_valid {
+        return Err(ServiceError::BadRequest {
+            error_message: "Invalid or expired nonce".to_string(),
+        });
+    }
+
+    let chain_id = message.chain_id as i64;
+    let existing = entities::user_wallet::Entity::find()
+        .filter(entities::user_wallet::Column::ChainId.eq(chain_id))
+        .filter(entities::user_wallet::Column::Address.eq(&message.address))
+        .one(&app_state.db)
+        .await?;
+
+    if existing.is_some() {
+        return Err(ServiceError::Conflict {
+            error_message: "Wallet is already linked to an account".to_string(),
+        });
+    }
+
+    let now = Utc::now().naive_utc();
+    let wallet_model = entities::user_wallet::ActiveModel {
+        user_id: Set(claims.id),
+        chain_id: Set(chain_id),
+        address: Set(message.address),
+        label: Set(wallet_data.label.trim().to_owned()),
+        verified_at: Set(now),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&app_state.db)
+    .await?;
+
+    Ok(api_response::ApiResponse::created(WalletModel::from(wallet_model)))
+}
+
+#[get("wallets")]
+pub async fn list_wallets(
+    app_state: web::Data<app_state::AppState>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse<Vec<WalletModel>>, ServiceError> {
+    let wallets = wallet::user_wallets(&app_state.db, claims.id)
+        .await?
+        .into_iter()
+        .map(WalletModel::from)
+        .collect::<Vec<WalletModel>>();
+
+    Ok(api_response::ApiResponse::ok(wallets))
+}
+
+#[delete("wallets/{wallet_id}")]
+pub async fn remove_wallet(
+    app_state: web::Data<app_state::AppState>,
+    wallet_id: web::Path<i32>,
+    claims: web::ReqData<Claims>,
+) -> Result<api_response::ApiResponse, ServiceError> {
+    let result = entities::user_wallet::Entity::delete_many()
+        .filter(entities::user_wallet::Column::Id.eq(wallet_id.into_inner()))
+        .filter(entities::user_wallet::Column::UserId.eq(claims.id))
+        .exec(&app_state.db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(ServiceError::NotFound {
+            error_message: "Wallet not found".to_string(),
+        });
+    }
+
+    Ok(api_response::ApiResponse::new(200, "Wallet removed".to_string()))
+}
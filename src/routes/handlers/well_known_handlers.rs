@@ -0,0 +1,9 @@
+use actix_web::{get, HttpResponse};
+
+use crate::utils::jwt_keys;
+
+/// Served bare, not in the API envelope: JWKS consumers expect RFC 7517 JSON.
+#[get("/jwks.json")]
+pub async fn jwks() -> HttpResponse {
+    HttpResponse::Ok().json(jwt_keys::KEYS.jwks())
+}
@@ -2,11 +2,18 @@ use actix_web::{
     body::MessageBody,
     dev::{ServiceRequest, ServiceResponse},
     http::header::AUTHORIZATION,
-    Error, HttpMessage,
+    web, Error, HttpMessage,
 };
 use actix_web_lab::middleware::Next;
 
-use crate::utils::{api_response, jwt::decode_jwt};
+use crate::error::ServiceError;
+use crate::utils::{
+    api_key,
+    app_state::AppState,
+    auth_cookie, constants,
+    jwt::{bearer_token, decode_jwt, TokenType},
+    session,
+};
 
 pub async fn check_auth_middleware(
     req: ServiceRequest,
@@ -14,25 +21,170 @@ pub async fn check_auth_middleware(
 ) -> Result<ServiceResponse<impl MessageBody>, Error> {
     let auth = req.headers().get(AUTHORIZATION);
 
-    if auth.is_none() {
-        return Err(Error::from(api_response::ApiResponse::new(
-            401,
-            "Unauthorized".to_string(),
-        )));
+    // machine clients may send an API key instead of a bearer token
+    if let (None, Some(key)) = (auth, req.headers().get(api_key::API_KEY_HEADER)) {
+        let key = key.to_str().unwrap_or_default().to_owned();
+        let app_state = req
+            .app_data::<web::Data<AppState>>()
+            .ok_or(ServiceError::InternalServerError {
+                error_message: "Missing app state".to_string(),
+            })?;
+
+        let claims = api_key::authenticate(&app_state.db, &key)
+            .await
+            .map_err(ServiceError::internal)?
+            .ok_or(ServiceError::Unauthorized {
+                error_message: "Invalid API key".to_string(),
+            })?;
+
+        req.extensions_mut().insert(claims);
+
+        return next.call(req).await;
     }
 
-    let token = auth
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .replace("Bearer ", "")
-        .to_owned();
-    let claim = decode_jwt(token).unwrap();
+    let token = if let Some(auth) = auth {
+        bearer_token(auth)?.to_owned()
+    } else if let Some(cookie) = req.cookie(&constants::AUTH_COOKIE_NAME) {
+        // browsers attach cookies to forged requests too, so writes need the double-submit token
+        if !auth_cookie::request_passes_csrf(req.request()) {
+            return Err(Error::from(ServiceError::Forbidden {
+                error_message: "Missing or invalid CSRF token".to_string(),
+            }));
+        }
 
-    req.extensions_mut().insert(claim);
+        cookie.value().to_owned()
+    } else {
+        return Err(Error::from(ServiceError::Unauthorized {
+            error_message: "Unauthorized".to_string(),
+        }));
+    };
+    let app_state = req
+        .app_data::<web::Data<AppState>>()
+        .ok_or(ServiceError::InternalServerError {
+            error_message: "Missing app state".to_string(),
+        })?;
 
-    next.call(req)
+    // tokens from a configured SSO issuer are verified against its JWKS and mapped to a local user
+    let claims = if let Some(issuer) = app_state.trusted_issuers.issuer_for(&token) {
+        app_state
+            .trusted_issuers
+            .authenticate(&app_state.db, issuer, &token)
+            .await
+            .map_err(ServiceError::from)?
+    } else {
+        decode_jwt(token).map_err(ServiceError::from)?.claims
+    };
+
+    if claims.typ != TokenType::Access {
+        return Err(Error::from(ServiceError::Unauthorized {
+            error_message: "Not an access token".to_string(),
+        }));
+    }
+
+    let token_valid = app_state
+        .revocations
+        .is_token_valid(&app_state.db, &claims)
         .await
-        .map_err(|err| Error::from(api_response::ApiResponse::new(500, err.to_string())))
+        .map_err(ServiceError::internal)?;
+
+    if !token_valid {
+        return Err(Error::from(ServiceError::Unauthorized {
+            error_message: "Token has been revoked".to_string(),
+        }));
+    }
+
+    // sessions that never refresh would otherwise look idle in the session list
+    if let Some(session_id) = claims.sid {
+        if let Err(err) = session::record_activity(&app_state.db, claims.id, session_id).await {
+            log::warn!("could not record activity of session {}: {}", session_id, err);
+        }
+    }
+
+    req.extensions_mut().insert(claims);
+
+    // errors of inner middlewares, like a 403 from `Authorize`, keep their status
+    next.call(req).await
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::middlewares::role_middleware::Authorize;
+    use crate::utils::{
+        blob_store::{DownloadSigner, LocalBlobStore}, jwt::encode_jwt, login_throttle::LoginThrottle, mailer::FileMailer,
+        mfa::SecretCipher, revocation::RevocationStore, roles::Role, test_support, trusted_issuers::TrustedIssuers,
+    };
+    use actix_web::{body::to_bytes, http::StatusCode, test, App, HttpResponse};
+    use actix_web_lab::middleware::from_fn;
+    use chrono::Utc;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use serde_json::{json, Value};
+
+    fn app_state(db: MockDatabase) -> web::Data<AppState> {
+        let issuer = test_support::sso_issuer_config(test_support::jwks_file());
+
+        web::Data::new(AppState {
+            db: db.into_connection(),
+            revocations: RevocationStore::new(),
+            login_throttle: LoginThrottle::new(),
+            mailer: Box::new(FileMailer::new("test@example.com".parse().unwrap(), None)),
+            trusted_issuers: TrustedIssuers::new(vec![issuer]).unwrap(),
+            blobs: Box::new(LocalBlobStore::new(std::env::temp_dir())),
+            download_links: DownloadSigner::new(&[7; 32]).unwrap(),
+            mfa_secrets: SecretCipher::new(&[7; 32]).unwrap(),
+        })
+    }
+
+    /// Status and problem `code` of a request to an admin-only route.
+    async fn call_admin_route(db: MockDatabase, token: &str) -> (StatusCode, String) {
+        let app = test::init_service(
+            App::new().app_data(app_state(db)).service(
+                web::scope("secure")
+                    .wrap(Authorize::role(Role::Admin))
+                    .wrap(from_fn(check_auth_middleware))
+                    .route("", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/secure")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+
+        let err = test::try_call_service(&app, req).await.map(|_| ()).unwrap_err();
+        let res = err.error_response();
+        let status = res.status();
+        let problem: Value = serde_json::from_slice(&to_bytes(res.into_body()).await.unwrap()).unwrap();
+
+        (status, problem["code"].as_str().unwrap_or_default().to_owned())
+    }
+
+    #[actix_rt::test]
+    async fn test_expired_sso_token_keeps_its_problem_code() {
+        let token = test_support::sso_token("sso-1", json!({ "exp": Utc::now().timestamp() - 120 }));
+
+        let (status, code) = call_admin_route(MockDatabase::new(DatabaseBackend::MySql), &token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(code, "token_expired");
+    }
+
+    #[actix_rt::test]
+    async fn test_sso_token_for_another_audience_keeps_its_problem_code() {
+        let token = test_support::sso_token("sso-1", json!({ "aud": "another_service" }));
+
+        let (status, code) = call_admin_route(MockDatabase::new(DatabaseBackend::MySql), &token).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(code, "invalid_audience");
+    }
+
+    #[actix_rt::test]
+    async fn test_forbidden_from_authorize_passes_through() {
+        let reader = test_support::user(1);
+        let token = encode_jwt(&reader, None).unwrap();
+        let db = MockDatabase::new(DatabaseBackend::MySql).append_query_results([vec![reader]]);
+
+        let (status, code) = call_admin_route(db, &token).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(code, "forbidden");
+    }
+}
@@ -0,0 +1,138 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage,
+};
+
+use crate::error::ServiceError;
+use crate::utils::{
+    jwt::Claims,
+    roles::{Permission, Role},
+};
+
+#[derive(Clone, Copy)]
+enum Requirement {
+    Role(Role),
+    Permission(Permission),
+    User,
+}
+
+/// Declarative access guard for routes behind `check_auth_middleware`.
+///
+/// Wrap a scope or resource with `Authorize::role(..)`, `Authorize::permission(..)`
+/// or `Authorize::user()` for routes that only make sense for a signed-in user;
+/// OAuth2 clients and API keys are turned away there.
+/// Middlewares run outermost-last, so register it *before* the auth middleware:
+///
+/// ```ignore
+/// web::scope("secure/block")
+///     .wrap(Authorize::permission(Permission::BlockWrite))
+///     .wrap(from_fn(auth_middleware::check_auth_middleware))
+/// ```
+#[derive(Clone, Copy)]
+pub struct Authorize {
+    requirement: Requirement,
+}
+
+impl Authorize {
+    pub fn role(role: Role) -> Self {
+        Authorize {
+            requirement: Requirement::Role(role),
+        }
+    }
+
+    pub fn permission(permission: Permission) -> Self {
+        Authorize {
+            requirement: Requirement::Permission(permission),
+        }
+    }
+
+    pub fn user() -> Self {
+        Authorize {
+            requirement: Requirement::User,
+        }
+    }
+
+    fn is_satisfied_by(&self, claims: &Claims) -> bool {
+        // OAuth2 clients have no role, their granted scopes are all they may do
+        let has_role = |allowed: bool| allowed || !claims.is_user();
+        // API keys are capped by their scopes on top of the owner's role
+        let in_scope = |permission: &Permission| {
+            claims
+                .scopes
+                .as_ref()
+                .is_none_or(|scopes| scopes.contains(permission))
+        };
+
+        match self.requirement {
+            Requirement::Role(role) => {
+                has_role(claims.role.includes(role)) && role.permissions().iter().all(in_scope)
+            }
+            Requirement::Permission(permission) => {
+                has_role(claims.role.has_permission(permission)) && in_scope(&permission)
+            }
+            Requirement::User => claims.is_account_holder(),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authorize
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = AuthorizeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthorizeMiddleware {
+            service,
+            authorize: *self,
+        }))
+    }
+}
+
+pub struct AuthorizeMiddleware<S> {
+    service: S,
+    authorize: Authorize,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthorizeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let allowed = req
+            .extensions()
+            .get::<Claims>()
+            .map(|claims| self.authorize.is_satisfied_by(claims));
+
+        match allowed {
+            Some(true) => Box::pin(self.service.call(req)),
+            Some(false) => Box::pin(ready(Err(Error::from(
+                ServiceError::Forbidden {
+                    error_message: "Forbidden".to_string(),
+                },
+            )))),
+            None => Box::pin(ready(Err(Error::from(
+                ServiceError::Unauthorized {
+                    error_message: "Unauthorized".to_string(),
+                },
+            )))),
+        }
+    }
+}
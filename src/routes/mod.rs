@@ -1,6 +1,9 @@
+pub mod admin_routes;
 pub mod auth_routes;
+pub mod blob_routes;
 pub mod block_routes;
 pub mod tx_routes;
 pub mod user_routes;
+pub mod well_known_routes;
 pub mod handlers;
 pub mod middlewares;        
\ No newline at end of file
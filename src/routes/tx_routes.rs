@@ -2,6 +2,8 @@ use actix_web::web;
 use actix_web_lab::middleware::from_fn;
 
 use crate::routes::middlewares;
+use crate::routes::middlewares::role_middleware::Authorize;
+use crate::utils::roles::Permission;
 
 use super::handlers::tx_handlers;
 
@@ -9,6 +11,8 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     cfg
     .service(
         web::scope("secure/tx")
+            // runs after the auth middleware, which is registered last
+            .wrap(Authorize::permission(Permission::TxWrite))
             .wrap(from_fn(middlewares::auth_middleware::check_auth_middleware))
             .service(tx_handlers::create_tx),
     )
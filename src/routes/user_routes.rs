@@ -1,15 +1,61 @@
-use actix_web::web;
+use actix_multipart::form::MultipartFormConfig;
+use actix_multipart::MultipartError;
+use actix_web::{web, Error, HttpRequest};
 use actix_web_lab::middleware::from_fn;
 
-use super::handlers::user_handlers;
+use super::handlers::{
+    account_handlers, api_key_handlers, avatar_handlers, mfa_handlers, session_handlers, user_handlers,
+    wallet_handlers,
+};
 use super::middlewares::auth_middleware;
+use super::middlewares::role_middleware::Authorize;
+use crate::error::ServiceError;
+use crate::utils::constants;
+
+fn upload_error(err: MultipartError, _req: &HttpRequest) -> Error {
+    ServiceError::from(err).into()
+}
+
+/// Uploads are buffered in memory up to `MAX_FILE_SIZE` and cut off beyond it.
+fn upload_config() -> MultipartFormConfig {
+    let limit = *constants::MAX_FILE_SIZE as usize;
+
+    MultipartFormConfig::default()
+        .total_limit(limit)
+        .memory_limit(limit)
+        .error_handler(upload_error)
+}
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/user").service(user_handlers::register))
+        .service(web::scope("/avatars").service(avatar_handlers::get_avatar))
         .service(
             web::scope("secure/user")
+                // neither OAuth2 clients nor API keys may manage the account
+                .wrap(Authorize::user())
                 .wrap(from_fn(auth_middleware::check_auth_middleware))
+                .app_data(upload_config())
                 .service(user_handlers::my_info)
-                .service(user_handlers::update_user_info),
+                .service(user_handlers::update_user_info)
+                .service(user_handlers::patch_user_info)
+                .service(user_handlers::change_password)
+                .service(user_handlers::export_account)
+                .service(user_handlers::export_account_link)
+                .service(user_handlers::delete_account)
+                .service(account_handlers::resend_verification_email)
+                .service(api_key_handlers::create_api_key)
+                .service(api_key_handlers::list_api_keys)
+                .service(api_key_handlers::revoke_api_key)
+                .service(mfa_handlers::enroll_totp)
+                .service(mfa_handlers::confirm_totp)
+                .service(mfa_handlers::disable_totp)
+                .service(session_handlers::list_sessions)
+                .service(session_handlers::revoke_session)
+                .service(session_handlers::revoke_other_sessions)
+                .service(wallet_handlers::wallet_challenge)
+                .service(wallet_handlers::add_wallet)
+                .service(wallet_handlers::list_wallets)
+                .service(wallet_handlers::remove_wallet)
+                .service(avatar_handlers::upload_avatar),
         );
 }
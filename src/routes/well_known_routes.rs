@@ -0,0 +1,7 @@
+use actix_web::web;
+
+use super::handlers::well_known_handlers;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::scope("/.well-known").service(well_known_handlers::jwks));
+}
@@ -0,0 +1,192 @@
+use chrono::{DateTime, Duration, Utc};
+use derive_more::Display;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use entities::action_token;
+
+use super::constants;
+use super::jwt_keys::KEYS;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl ActionPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActionPurpose::VerifyEmail => "verify_email",
+            ActionPurpose::ResetPassword => "reset_password",
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        match self {
+            ActionPurpose::VerifyEmail => Duration::hours(*constants::EMAIL_VERIFICATION_TTL_HOURS),
+            ActionPurpose::ResetPassword => Duration::minutes(*constants::PASSWORD_RESET_TTL_MINUTES),
+        }
+    }
+}
+
+/// Claims of an emailed link token. The shape differs from access token
+/// claims, so neither kind decodes as the other.
+#[derive(Serialize, Deserialize)]
+struct ActionClaims {
+    sub: i32,
+    purpose: ActionPurpose,
+    jti: String,
+    aud: String,
+    iat: usize,
+    exp: usize,
+}
+
+#[derive(Debug, Display)]
+pub enum ActionTokenError {
+    #[display(fmt = "Invalid or expired token")]
+    Invalid,
+
+    #[display(fmt = "{_0}")]
+    Db(DbErr),
+
+    #[display(fmt = "{_0}")]
+    Jwt(jsonwebtoken::errors::Error),
+}
+
+impl From<DbErr> for ActionTokenError {
+    fn from(err: DbErr) -> Self {
+        ActionTokenError::Db(err)
+    }
+}
+
+fn encode_action_token(
+    user_id: i32,
+    purpose: ActionPurpose,
+    jti: &str,
+    now: DateTime<Utc>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    KEYS.encode(&ActionClaims {
+        sub: user_id,
+        purpose,
+        jti: jti.to_owned(),
+        aud: constants::JWT_AUDIENCE.clone(),
+        iat: now.timestamp() as usize,
+        exp: (now + purpose.ttl()).timestamp() as usize,
+    })
+}
+
+/// Signs a token for `purpose` and records its `jti` so it can be used once.
+pub async fn issue_action_token(
+    db: &DatabaseConnection,
+    user_id: i32,
+    purpose: ActionPurpose,
+) -> Result<String, ActionTokenError> {
+    let now = Utc::now();
+    let jti = Uuid::new_v4().to_string();
+    let token = encode_action_token(user_id, purpose, &jti, now).map_err(ActionTokenError::Jwt)?;
+
+    action_token::ActiveModel {
+        jti: Set(jti),
+        user_id: Set(user_id),
+        purpose: Set(purpose.as_str().to_owned()),
+        expires_at: Set((now + purpose.ttl()).naive_utc()),
+        used_at: Set(None),
+        created_at: Set(now.naive_utc()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(token)
+}
+
+/// Verifies `token` was issued for `purpose`, marks it used and returns the user id.
+pub async fn consume_action_token(
+    db: &DatabaseConnection,
+    token: &str,
+    purpose: ActionPurpose,
+) -> Result<i32, ActionTokenError> {
+    let claims = KEYS
+        .decode::<ActionClaims>(token)
+        .map_err(|_| ActionTokenError::Invalid)?
+        .claims;
+
+    if claims.purpose != purpose {
+        return Err(ActionTokenError::Invalid);
+    }
+
+    let now = Utc::now().naive_utc();
+    let result = action_token::Entity::update_many()
+        .col_expr(action_token::Column::UsedAt, Expr::value(now))
+        .filter(action_token::Column::Jti.eq(claims.jti))
+        .filter(action_token::Column::UserId.eq(claims.sub))
+        .filter(action_token::Column::Purpose.eq(purpose.as_str()))
+        .filter(action_token::Column::UsedAt.is_null())
+        .filter(action_token::Column::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    if result.rows_affected != 1 {
+        return Err(ActionTokenError::Invalid);
+    }
+
+    Ok(claims.sub)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+
+    fn mock_db(rows_affected: u64) -> DatabaseConnection {
+        MockDatabase::new(DatabaseBackend::MySql)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected,
+            }])
+            .into_connection()
+    }
+
+    #[actix_rt::test]
+    async fn test_consume_action_token() {
+        let token = encode_action_token(7, ActionPurpose::ResetPassword, "jti-1", Utc::now()).unwrap();
+
+        let user_id = consume_action_token(&mock_db(1), &token, ActionPurpose::ResetPassword)
+            .await
+            .unwrap();
+
+        assert_eq!(user_id, 7);
+    }
+
+    #[actix_rt::test]
+    async fn test_used_token_is_rejected() {
+        let token = encode_action_token(7, ActionPurpose::ResetPassword, "jti-1", Utc::now()).unwrap();
+
+        let result = consume_action_token(&mock_db(0), &token, ActionPurpose::ResetPassword).await;
+
+        assert!(matches!(result, Err(ActionTokenError::Invalid)));
+    }
+
+    #[actix_rt::test]
+    async fn test_token_for_other_purpose_is_rejected() {
+        let token = encode_action_token(7, ActionPurpose::VerifyEmail, "jti-1", Utc::now()).unwrap();
+
+        let result = consume_action_token(&mock_db(1), &token, ActionPurpose::ResetPassword).await;
+
+        assert!(matches!(result, Err(ActionTokenError::Invalid)));
+    }
+
+    #[actix_rt::test]
+    async fn test_expired_token_is_rejected() {
+        let issued = Utc::now() - Duration::days(7);
+        let token = encode_action_token(7, ActionPurpose::ResetPassword, "jti-1", issued).unwrap();
+
+        let result = consume_action_token(&mock_db(1), &token, ActionPurpose::ResetPassword).await;
+
+        assert!(matches!(result, Err(ActionTokenError::Invalid)));
+    }
+}
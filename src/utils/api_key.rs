@@ -0,0 +1,178 @@
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use uuid::Uuid;
+
+use entities::{api_key, user_info};
+
+use super::jwt::{Claims, TokenType};
+use super::roles::Permission;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+pub struct GeneratedApiKey {
+    /// Shown to the user once, never stored.
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+}
+
+/// Keys look like `rsk_<prefix>_<secret>`; the prefix lets users tell keys apart in listings.
+pub fn generate_api_key() -> GeneratedApiKey {
+    let prefix = Uuid::new_v4().simple().to_string()[..8].to_owned();
+    let key = format!(
+        "rsk_{}_{}{}",
+        prefix,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+
+    GeneratedApiKey {
+        hash: hash_api_key(&key),
+        key,
+        prefix,
+    }
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    sha256::digest(key)
+}
+
+pub fn format_scopes(scopes: &[Permission]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+pub fn parse_scopes(scopes: &str) -> Result<Vec<Permission>, String> {
+    scopes
+        .split(',')
+        .filter(|scope| !scope.is_empty())
+        .map(|scope| scope.parse::<Permission>())
+        .collect()
+}
+
+/// Resolves an `X-Api-Key` value to the same principal a JWT would produce,
+/// restricted to the scopes of the key.
+pub async fn authenticate(db: &DatabaseConnection, key: &str) -> Result<Option<Claims>, DbErr> {
+    let now = Utc::now().naive_utc();
+
+    let api_key = match api_key::Entity::find()
+        .filter(api_key::Column::KeyHash.eq(hash_api_key(key)))
+        .filter(api_key::Column::RevokedAt.is_null())
+        .one(db)
+        .await?
+    {
+        Some(api_key) => api_key,
+        None => return Ok(None),
+    };
+
+    if api_key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Ok(None);
+    }
+
+    let user = match user_info::Entity::find_by_id(api_key.user_id).one(db).await? {
+        Some(user) if user.disabled_at.is_none() && user.deleted_at.is_none() => user,
+        _ => return Ok(None),
+    };
+
+    // keep the write rate low for chatty ingestion scripts
+    if api_key
+        .last_used_at
+        .is_none_or(|last_used_at| now - last_used_at > Duration::minutes(1))
+    {
+        api_key::Entity::update_many()
+            .col_expr(api_key::Column::LastUsedAt, Expr::value(now))
+            .filter(api_key::Column::Id.eq(api_key.id))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(Some(Claims {
+        exp: api_key
+            .expires_at
+            .map_or(usize::MAX, |expires_at| expires_at.and_utc().timestamp() as usize),
+        iat: now.and_utc().timestamp() as usize,
+        email: user.email,
+        id: user.id,
+        jti: format!("api-key-{}", api_key.id),
+        ver: user.token_version,
+        role: user.role.parse().unwrap_or_default(),
+        scopes: Some(parse_scopes(&api_key.scopes).unwrap_or_default()),
+        typ: TokenType::Access,
+        sid: None,
+        sub: format!("user:{}", user.id),
+        aud: None,
+        nbf: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+
+    #[test]
+    fn test_generate_api_key() {
+        let generated = generate_api_key();
+
+        assert!(generated.key.starts_with(&format!("rsk_{}_", generated.prefix)));
+        assert_eq!(generated.hash, hash_api_key(&generated.key));
+        assert_ne!(generated.key, generate_api_key().key);
+    }
+
+    #[test]
+    fn test_scopes_round_trip() {
+        let scopes = vec![Permission::BlockWrite, Permission::TxRead];
+        let formatted = format_scopes(&scopes);
+
+        assert_eq!(formatted, "block:write,tx:read");
+        assert_eq!(parse_scopes(&formatted).unwrap(), scopes);
+        assert!(parse_scopes("").unwrap().is_empty());
+        assert!(parse_scopes("block:write,nope").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_authenticate_builds_scoped_claims() {
+        let now = Utc::now();
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![api_key::Model {
+                id: 5,
+                user_id: 42,
+                name: "indexer".to_string(),
+                prefix: "abcd1234".to_string(),
+                key_hash: hash_api_key("rsk_abcd1234_secret"),
+                scopes: "block:write".to_string(),
+                expires_at: None,
+                last_used_at: Some(now.naive_utc()),
+                revoked_at: None,
+                created_at: now.naive_utc(),
+            }]])
+            .append_query_results([vec![user_info::Model {
+                name: "Indexer".to_string(),
+                email: "indexer@example.com".to_string(),
+                role: "ingestor".to_string(),
+                ..test_support::user(42)
+            }]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+
+        let claims = authenticate(&db, "rsk_abcd1234_secret")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(claims.id, 42);
+        assert_eq!(claims.jti, "api-key-5");
+        assert_eq!(claims.scopes, Some(vec![Permission::BlockWrite]));
+        // a key acts for its owner but may never manage the account
+        assert!(claims.is_user());
+        assert!(!claims.is_account_holder());
+    }
+}
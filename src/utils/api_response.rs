@@ -1,76 +1,147 @@
-use std::fmt::Display;
+use std::fmt::Debug;
 
-use actix_web::{body::BoxBody, http::StatusCode, web, HttpResponse, Responder, ResponseError};
+use actix_web::{
+    body::BoxBody,
+    http::{header::ContentType, StatusCode},
+    HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
+use crate::error::ServiceError;
 
+/// Payload of responses that only carry a human readable message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    pub message: String,
+}
+
+/// The one shape of every successful JSON response; `meta` carries things
+/// like pagination. Errors are problem documents, see `ServiceError`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope<T> {
+    pub data: T,
+    pub meta: Option<Value>,
+}
+
+/// A JSON response with a typed payload. Handlers return
+/// `Result<ApiResponse<Payload>, ServiceError>`.
 #[derive(Debug)]
-pub struct ApiResponse{
+pub struct ApiResponse<T = Message> {
     pub status_code: u16,
-    pub body: String,
-    response_code: StatusCode
+    pub body: Envelope<T>,
+    response_code: StatusCode,
+    headers: Vec<(String, String)>,
+}
+
+impl ApiResponse<Message> {
+    /// A response that only carries a message, like `{ "message": "success" }`.
+    pub fn new(status_code: u16, message: String) -> Self {
+        Self::with_data(status_code, Message { message })
+    }
 }
 
-impl ApiResponse{
-    pub fn new(status_code: u16, body: String) -> Self {
-        ApiResponse{
+impl<T: Serialize> ApiResponse<T> {
+    pub fn with_data(status_code: u16, data: T) -> Self {
+        ApiResponse {
             status_code,
-            body,
-            response_code: StatusCode::from_u16(status_code).unwrap()
+            body: Envelope { data, meta: None },
+            response_code: StatusCode::from_u16(status_code).unwrap(),
+            headers: Vec::new(),
         }
     }
-}
 
-impl Responder for ApiResponse{
-    type Body = BoxBody;
+    pub fn ok(data: T) -> Self {
+        Self::with_data(200, data)
+    }
 
-    fn respond_to(self, _req: &actix_web::HttpRequest) -> actix_web::HttpResponse<Self::Body> {
-        let body = BoxBody::new(web::BytesMut::from(self.body.as_bytes()));
-        HttpResponse::new(self.response_code).set_body(body)
+    pub fn created(data: T) -> Self {
+        Self::with_data(201, data)
     }
-}
 
-impl Display for ApiResponse{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f,"Error: {} \n Status Code: {}",self.body,self.status_code)
+    pub fn with_meta(mut self, meta: Value) -> Self {
+        self.body.meta = Some(meta);
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: String) -> Self {
+        self.headers.push((name.to_owned(), value));
+        self
     }
-}
 
-impl ResponseError for ApiResponse {
-    fn status_code(&self) -> StatusCode {
-        self.response_code
+    fn to_http_response(&self) -> HttpResponse<BoxBody> {
+        let body = match serde_json::to_vec(&self.body) {
+            Ok(body) => body,
+            Err(err) => return ServiceError::internal(err).error_response(),
+        };
+
+        let mut builder = HttpResponse::build(self.response_code);
+        builder.insert_header(ContentType::json());
+        for (name, value) in &self.headers {
+            builder.append_header((name.as_str(), value.as_str()));
+        }
+
+        builder.body(body)
     }
+}
 
-    fn error_response(&self) -> HttpResponse<BoxBody> {
-        let body = BoxBody::new(web::BytesMut::from(self.body.as_bytes()));
-        HttpResponse::new(self.status_code()).set_body(body)
+impl<T: Serialize> Responder for ApiResponse<T> {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        self.to_http_response()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use actix_web::body::to_bytes;
+    use actix_web::http::header::CONTENT_TYPE;
 
     #[test]
     fn test_api_response_creation() {
         let response = ApiResponse::new(200, "Success".to_string());
         assert_eq!(response.status_code, 200);
-        assert_eq!(response.body, "Success");
+        assert_eq!(response.body.data, Message { message: "Success".to_string() });
+        assert_eq!(response.body.meta, None);
     }
 
     #[test]
     fn test_api_response_status_codes() {
-        let response_400 = ApiResponse::new(400, "Bad Request".to_string());
-        assert_eq!(response_400.status_code, 400);
-        
-        let response_404 = ApiResponse::new(404, "Not Found".to_string());
-        assert_eq!(response_404.status_code, 404);
+        let response_201 = ApiResponse::created(Message { message: "Created".to_string() });
+        assert_eq!(response_201.status_code, 201);
+
+        let response_202 = ApiResponse::new(202, "Accepted".to_string());
+        assert_eq!(response_202.status_code, 202);
     }
 
     #[test]
-    fn test_api_response_display() {
-        let response = ApiResponse::new(404, "Resource not found".to_string());
-        let display_string = format!("{}", response);
-        assert!(display_string.contains("Error: Resource not found"));
-        assert!(display_string.contains("Status Code: 404"));
+    fn test_api_response_headers() {
+        let response = ApiResponse::new(200, "success".to_string())
+            .with_header("ETag", "\"1\"".to_string())
+            .to_http_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("ETag").unwrap(), "\"1\"");
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "application/json");
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn test_typed_payload_is_enveloped() {
+        #[derive(Serialize)]
+        struct Token {
+            token: String,
+        }
+
+        let response = ApiResponse::ok(Token { token: "abc".to_string() })
+            .with_meta(serde_json::json!({ "page": 1 }))
+            .to_http_response();
+        let body = to_bytes(response.into_body()).await.unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            serde_json::json!({ "data": { "token": "abc" }, "meta": { "page": 1 } })
+        );
+    }
+}
@@ -1,5 +1,19 @@
 use sea_orm::DatabaseConnection;
 
+use super::blob_store::{BlobStore, DownloadSigner};
+use super::login_throttle::LoginThrottle;
+use super::mailer::Mailer;
+use super::mfa::SecretCipher;
+use super::revocation::RevocationStore;
+use super::trusted_issuers::TrustedIssuers;
+
 pub struct AppState {
-    pub db: DatabaseConnection
-}
\ No newline at end of file
+    pub db: DatabaseConnection,
+    pub revocations: RevocationStore,
+    pub login_throttle: LoginThrottle,
+    pub mailer: Box<dyn Mailer>,
+    pub trusted_issuers: TrustedIssuers,
+    pub blobs: Box<dyn BlobStore>,
+    pub download_links: DownloadSigner,
+    pub mfa_secrets: SecretCipher,
+}
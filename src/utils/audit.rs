@@ -0,0 +1,126 @@
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use serde_json::Value;
+
+use entities::audit_log;
+
+use super::jwt::Claims;
+
+/// Administrative changes to an account. The stored names are stable, so
+/// variants may be added but never renamed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuditAction {
+    DisableUser,
+    EnableUser,
+    ForcePasswordReset,
+    ChangeRole,
+    DeleteUser,
+    RestoreUser,
+    UnlockUser,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::DisableUser => "user.disable",
+            AuditAction::EnableUser => "user.enable",
+            AuditAction::ForcePasswordReset => "user.force_password_reset",
+            AuditAction::ChangeRole => "user.change_role",
+            AuditAction::DeleteUser => "user.delete",
+            AuditAction::RestoreUser => "user.restore",
+            AuditAction::UnlockUser => "user.unlock",
+        }
+    }
+}
+
+/// Records who did what to which account. `details` is kept after the target
+/// is purged, so it must hold ids and settings only, never personal data.
+pub async fn record(
+    db: &DatabaseConnection,
+    actor: &Claims,
+    action: AuditAction,
+    target_user_id: i32,
+    details: Value,
+) -> Result<audit_log::Model, DbErr> {
+    audit_log::ActiveModel {
+        actor: Set(actor.sub.clone()),
+        action: Set(action.as_str().to_owned()),
+        target_user_id: Set(target_user_id),
+        details: Set(details.to_string()),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// Newest entries about one account first.
+pub async fn user_history(
+    db: &DatabaseConnection,
+    target_user_id: i32,
+    limit: u64,
+) -> Result<Vec<audit_log::Model>, DbErr> {
+    audit_log::Entity::find()
+        .filter(audit_log::Column::TargetUserId.eq(target_user_id))
+        .order_by_desc(audit_log::Column::Id)
+        .limit(limit)
+        .all(db)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use serde_json::json;
+
+    #[actix_rt::test]
+    async fn test_record_stores_actor_and_action() {
+        let now = Utc::now().naive_utc();
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 1,
+                rows_affected: 1,
+            }])
+            .append_query_results([vec![audit_log::Model {
+                id: 1,
+                actor: "user:1".to_string(),
+                action: "user.change_role".to_string(),
+                target_user_id: 7,
+                details: r#"{"role":"admin"}"#.to_string(),
+                created_at: now,
+            }]])
+            .into_connection();
+
+        let actor = Claims {
+            exp: 0,
+            iat: 0,
+            email: "admin@example.com".to_string(),
+            id: 1,
+            jti: "jti".to_string(),
+            ver: 0,
+            role: Default::default(),
+            scopes: None,
+            typ: Default::default(),
+            sid: None,
+            sub: "user:1".to_string(),
+            aud: None,
+            nbf: None,
+        };
+
+        let entry = record(&db, &actor, AuditAction::ChangeRole, 7, json!({ "role": "admin" }))
+            .await
+            .unwrap();
+
+        assert_eq!(entry.action, "user.change_role");
+
+        let log = db.into_transaction_log();
+        let insert = format!("{:?}", log[0]);
+        assert!(insert.contains("user:1"));
+        assert!(insert.contains("user.change_role"));
+        assert!(insert.contains(r#"{\"role\":\"admin\"}"#));
+    }
+}
@@ -0,0 +1,157 @@
+use actix_web::cookie::time::Duration as CookieDuration;
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::Method;
+use actix_web::{web, HttpRequest};
+use chrono::Duration;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::constants;
+
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// How a login hands its tokens to the client. Browser clients opt into
+/// cookies with `?mode=cookie`; everyone else keeps getting bearer tokens.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    #[default]
+    Bearer,
+    Cookie,
+}
+
+#[derive(Deserialize)]
+struct AuthModeQuery {
+    #[serde(default)]
+    mode: AuthMode,
+}
+
+impl AuthMode {
+    pub fn from_query(query: &str) -> Self {
+        web::Query::<AuthModeQuery>::from_query(query)
+            .map(|query| query.mode)
+            .unwrap_or_default()
+    }
+}
+
+pub fn generate_csrf_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn same_site() -> SameSite {
+    match constants::COOKIE_SAME_SITE.to_ascii_lowercase().as_str() {
+        "lax" => SameSite::Lax,
+        "none" => SameSite::None,
+        _ => SameSite::Strict,
+    }
+}
+
+fn build_cookie(name: &str, value: String, path: &str, max_age: Duration, http_only: bool) -> Cookie<'static> {
+    let mut cookie = Cookie::build(name.to_owned(), value)
+        .path(path.to_owned())
+        .http_only(http_only)
+        .secure(*constants::COOKIE_SECURE)
+        .same_site(same_site())
+        .max_age(CookieDuration::seconds(max_age.num_seconds()))
+        .finish();
+
+    if let Some(domain) = constants::COOKIE_DOMAIN.as_ref() {
+        cookie.set_domain(domain.clone());
+    }
+
+    cookie
+}
+
+/// Cookies of a browser session. The CSRF cookie is readable by scripts on
+/// purpose: the dashboard echoes it in the `X-CSRF-Token` header.
+pub fn session_cookies(access_token: String, refresh_token: String, csrf_token: String) -> Vec<Cookie<'static>> {
+    let refresh_ttl = Duration::days(*constants::REFRESH_TOKEN_TTL_DAYS);
+
+    vec![
+        build_cookie(
+            &constants::AUTH_COOKIE_NAME,
+            access_token,
+            "/",
+            Duration::minutes(*constants::ACCESS_TOKEN_TTL_MINUTES),
+            true,
+        ),
+        // only the /auth endpoints ever need the refresh token
+        build_cookie(&constants::REFRESH_COOKIE_NAME, refresh_token, "/auth", refresh_ttl, true),
+        build_cookie(&constants::CSRF_COOKIE_NAME, csrf_token, "/", refresh_ttl, false),
+    ]
+}
+
+pub fn removal_cookies() -> Vec<Cookie<'static>> {
+    [
+        (constants::AUTH_COOKIE_NAME.as_str(), "/"),
+        (constants::REFRESH_COOKIE_NAME.as_str(), "/auth"),
+        (constants::CSRF_COOKIE_NAME.as_str(), "/"),
+    ]
+    .into_iter()
+    .map(|(name, path)| build_cookie(name, String::new(), path, Duration::zero(), true))
+    .collect()
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Safe methods never need a token; everything else must echo the CSRF cookie in the header.
+pub fn csrf_satisfied(method: &Method, cookie: Option<&str>, header: Option<&str>) -> bool {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return true;
+    }
+
+    match (cookie, header) {
+        (Some(cookie), Some(header)) => !cookie.is_empty() && constant_time_eq(cookie.as_bytes(), header.as_bytes()),
+        _ => false,
+    }
+}
+
+/// Double-submit check for a request authenticated by cookie.
+pub fn request_passes_csrf(req: &HttpRequest) -> bool {
+    let cookie = req.cookie(&constants::CSRF_COOKIE_NAME);
+    let header = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    csrf_satisfied(req.method(), cookie.as_ref().map(|cookie| cookie.value()), header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_mode_from_query() {
+        assert_eq!(AuthMode::from_query("mode=cookie"), AuthMode::Cookie);
+        assert_eq!(AuthMode::from_query("mode=bearer"), AuthMode::Bearer);
+        assert_eq!(AuthMode::from_query(""), AuthMode::Bearer);
+        assert_eq!(AuthMode::from_query("mode=nonsense"), AuthMode::Bearer);
+    }
+
+    #[test]
+    fn test_session_cookies_flags() {
+        let cookies = session_cookies("access".to_string(), "refresh".to_string(), "csrf".to_string());
+
+        let access = &cookies[0];
+        assert_eq!(access.name(), constants::AUTH_COOKIE_NAME.as_str());
+        assert_eq!(access.http_only(), Some(true));
+        assert_eq!(access.secure(), Some(*constants::COOKIE_SECURE));
+        assert_eq!(access.same_site(), Some(SameSite::Strict));
+
+        assert_eq!(cookies[1].path(), Some("/auth"));
+        assert_eq!(cookies[2].http_only(), Some(false));
+    }
+
+    #[test]
+    fn test_csrf_double_submit() {
+        assert!(csrf_satisfied(&Method::GET, None, None));
+        assert!(csrf_satisfied(&Method::POST, Some("abc"), Some("abc")));
+        assert!(!csrf_satisfied(&Method::POST, Some("abc"), Some("abd")));
+        assert!(!csrf_satisfied(&Method::DELETE, Some("abc"), None));
+        assert!(!csrf_satisfied(&Method::PATCH, None, Some("abc")));
+        assert!(!csrf_satisfied(&Method::PUT, Some(""), Some("")));
+    }
+}
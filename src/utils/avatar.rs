@@ -0,0 +1,119 @@
+use std::io::Cursor;
+
+use derive_more::Display;
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader, Limits};
+
+/// Square thumbnail edges in pixels; the first one is the profile image.
+pub const AVATAR_SIZES: [u32; 2] = [256, 64];
+
+/// Larger images are refused before decoding, so a tiny file cannot expand
+/// into gigabytes of pixels.
+const MAX_DIMENSION: u32 = 8192;
+
+#[derive(Debug, Display, PartialEq)]
+pub enum AvatarError {
+    #[display(fmt = "Only PNG, JPEG, GIF and WebP images are allowed")]
+    UnsupportedType,
+
+    #[display(fmt = "Invalid image: {_0}")]
+    Invalid(String),
+}
+
+/// Format from the magic bytes; the client's `Content-Type` and file name are ignored.
+pub fn sniff_format(data: &[u8]) -> Result<ImageFormat, AvatarError> {
+    match image::guess_format(data) {
+        Ok(format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP)) => Ok(format),
+        _ => Err(AvatarError::UnsupportedType),
+    }
+}
+
+/// Decodes the upload and renders every size in `AVATAR_SIZES` as PNG.
+/// Re-encoding from pixels drops EXIF and any other metadata of the original.
+pub fn thumbnails(data: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, AvatarError> {
+    let format = sniff_format(data)?;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let image = reader
+        .decode()
+        .map_err(|err| AvatarError::Invalid(err.to_string()))?;
+
+    AVATAR_SIZES
+        .iter()
+        .map(|size| {
+            let mut png = Vec::new();
+            image
+                .resize_to_fill(*size, *size, FilterType::Lanczos3)
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(|err| AvatarError::Invalid(err.to_string()))?;
+
+            Ok((*size, png))
+        })
+        .collect()
+}
+
+/// Blob key of one thumbnail. Each upload gets a new `avatar_id`, so stored
+/// thumbnails never change and can be cached forever.
+pub fn avatar_key(user_id: i32, avatar_id: &str, size: u32) -> String {
+    format!("avatars/{}/{}/{}.png", user_id, avatar_id, size)
+}
+
+/// `avatar_id` of an image URL that points at one of the user's uploads.
+pub fn avatar_id_from_url(user_id: i32, url: &str) -> Option<String> {
+    let rest = url.strip_prefix(&format!("/avatars/{}/", user_id))?;
+    let (avatar_id, _) = rest.split_once('/')?;
+
+    Some(avatar_id.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GenericImageView, RgbImage};
+
+    fn encoded(format: ImageFormat, width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut data), format)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_sniff_format() {
+        assert_eq!(sniff_format(&encoded(ImageFormat::Jpeg, 4, 4)), Ok(ImageFormat::Jpeg));
+        assert_eq!(sniff_format(&encoded(ImageFormat::Png, 4, 4)), Ok(ImageFormat::Png));
+        assert_eq!(sniff_format(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"), Err(AvatarError::UnsupportedType));
+        assert_eq!(sniff_format(b"GIF"), Err(AvatarError::UnsupportedType));
+    }
+
+    #[test]
+    fn test_thumbnails_are_square_pngs() {
+        let thumbnails = thumbnails(&encoded(ImageFormat::Jpeg, 640, 480)).unwrap();
+
+        assert_eq!(thumbnails.len(), AVATAR_SIZES.len());
+        for (size, png) in thumbnails {
+            assert_eq!(sniff_format(&png), Ok(ImageFormat::Png));
+            assert_eq!(image::load_from_memory(&png).unwrap().dimensions(), (size, size));
+        }
+    }
+
+    #[test]
+    fn test_truncated_image_is_invalid() {
+        let data = encoded(ImageFormat::Png, 64, 64);
+
+        assert!(matches!(thumbnails(&data[..40]), Err(AvatarError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_avatar_id_from_url() {
+        assert_eq!(avatar_id_from_url(7, "/avatars/7/abc/256.png").as_deref(), Some("abc"));
+        assert_eq!(avatar_id_from_url(8, "/avatars/7/abc/256.png"), None);
+        assert_eq!(avatar_id_from_url(7, "https://example.com/me.png"), None);
+    }
+}
@@ -0,0 +1,167 @@
+use derive_more::Display;
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use sha3::{Digest, Keccak256};
+
+#[derive(Debug, Display, PartialEq)]
+pub enum EthError {
+    #[display(fmt = "Invalid signature encoding")]
+    InvalidSignature,
+
+    #[display(fmt = "Signature does not recover to a public key")]
+    RecoveryFailed,
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Hash signed by `personal_sign` / `eth_sign` (EIP-191 version 0x45).
+pub fn eip191_hash(message: &str) -> [u8; 32] {
+    let prefixed = format!(
+        "\x19Ethereum Signed Message:\n{}{}",
+        message.len(),
+        message
+    );
+
+    keccak256(prefixed.as_bytes())
+}
+
+pub fn public_key_to_address(key: &VerifyingKey) -> String {
+    let encoded = key.to_encoded_point(false);
+    let hash = keccak256(&encoded.as_bytes()[1..]);
+
+    format!("0x{}", hex::encode(&hash[12..]))
+}
+
+/// Lowercased `0x` address, or `None` when `address` is not 20 hex bytes.
+pub fn normalize_address(address: &str) -> Option<String> {
+    let hex_part = address.strip_prefix("0x").or(address.strip_prefix("0X"))?;
+
+    if hex_part.len() != 40 || !hex_part.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(format!("0x{}", hex_part.to_ascii_lowercase()))
+}
+
+/// EIP-55 mixed-case checksum encoding of an address.
+pub fn to_checksum_address(address: &str) -> Option<String> {
+    let lower = normalize_address(address)?;
+    let hex_part = &lower[2..];
+    let hash = hex::encode(keccak256(hex_part.as_bytes()));
+
+    let checksummed = hex_part
+        .chars()
+        .zip(hash.chars())
+        .map(|(c, h)| {
+            if c.is_ascii_alphabetic() && h.to_digit(16).unwrap_or(0) >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+
+    Some(format!("0x{}", checksummed))
+}
+
+/// Recovers the lowercased address that produced a 65-byte `personal_sign` signature.
+pub fn recover_address(message: &str, signature: &str) -> Result<String, EthError> {
+    let bytes = hex::decode(signature.trim_start_matches("0x"))
+        .map_err(|_| EthError::InvalidSignature)?;
+
+    if bytes.len() != 65 {
+        return Err(EthError::InvalidSignature);
+    }
+
+    // wallets use either 27/28 or 0/1 for the recovery byte
+    let v = match bytes[64] {
+        27 | 28 => bytes[64] - 27,
+        0 | 1 => bytes[64],
+        _ => return Err(EthError::InvalidSignature),
+    };
+
+    let signature = Signature::from_slice(&bytes[..64]).map_err(|_| EthError::InvalidSignature)?;
+    let recovery_id = RecoveryId::from_byte(v).ok_or(EthError::InvalidSignature)?;
+
+    let key = VerifyingKey::recover_from_prehash(&eip191_hash(message), &signature, recovery_id)
+        .map_err(|_| EthError::RecoveryFailed)?;
+
+    Ok(public_key_to_address(&key))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use k256::ecdsa::SigningKey;
+
+    pub(crate) fn sign_message(key: &SigningKey, message: &str) -> String {
+        let (signature, recovery_id) = key
+            .sign_prehash_recoverable(&eip191_hash(message))
+            .unwrap();
+
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(recovery_id.to_byte() + 27);
+
+        format!("0x{}", hex::encode(bytes))
+    }
+
+    pub(crate) fn test_key() -> SigningKey {
+        let secret =
+            hex::decode("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")
+                .unwrap();
+
+        SigningKey::from_slice(&secret).unwrap()
+    }
+
+    #[test]
+    fn test_public_key_to_address() {
+        assert_eq!(
+            public_key_to_address(test_key().verifying_key()),
+            "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"
+        );
+    }
+
+    #[test]
+    fn test_recover_address() {
+        let signature = sign_message(&test_key(), "hello");
+
+        assert_eq!(
+            recover_address("hello", &signature).unwrap(),
+            "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"
+        );
+        assert_ne!(
+            recover_address("hello!", &signature).unwrap(),
+            "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23"
+        );
+        assert_eq!(
+            recover_address("hello", "0x1234"),
+            Err(EthError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_checksum_address() {
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        ] {
+            assert_eq!(
+                to_checksum_address(&address.to_lowercase()).as_deref(),
+                Some(address)
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_address() {
+        assert_eq!(
+            normalize_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").as_deref(),
+            Some("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+        );
+        assert!(normalize_address("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_none());
+        assert!(normalize_address("0x5aAeb6053F").is_none());
+        assert!(normalize_address("0xZZAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_none());
+    }
+}
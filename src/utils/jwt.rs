@@ -1,19 +1,82 @@
 use std::future;
 
+use actix_web::http::header::HeaderValue;
 use actix_web::{FromRequest, HttpMessage};
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use jsonwebtoken::TokenData;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use super::constants;
+use super::jwt_keys::KEYS;
+use crate::error::ServiceError;
+use super::roles::{Permission, Role};
 
 
+/// What a token may be used for. Only access tokens pass `check_auth_middleware`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    #[default]
+    Access,
+    /// Proves the password step of a login; only `/auth/mfa` accepts it.
+    MfaPending,
+}
+
 #[derive(Serialize,Deserialize,Clone)]
 pub struct Claims{
     pub exp: usize,
     pub iat: usize,
     pub email: String,
-    pub id: i32
+    pub id: i32,
+    pub jti: String,
+    pub ver: i32,
+    #[serde(default)]
+    pub role: Role,
+    /// Set when the principal is an API key, which may only use these permissions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<Permission>>,
+    #[serde(default)]
+    pub typ: TokenType,
+    /// Login session the token belongs to, see `utils::session`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<i32>,
+    /// `user:<id>` or `client:<client_id>`, see `Claims::principal`.
+    #[serde(default)]
+    pub sub: String,
+    /// `JWT_AUDIENCE` for tokens we issue; `decode` rejects a token without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<usize>,
+}
+
+/// Who a token was issued to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Principal<'a> {
+    User(i32),
+    /// An OAuth2 client authenticated with the client_credentials grant.
+    Client(&'a str),
+}
+
+impl Claims {
+    pub fn principal(&self) -> Principal<'_> {
+        match self.sub.strip_prefix("client:") {
+            Some(client_id) => Principal::Client(client_id),
+            // tokens issued before `sub` existed always belong to a user
+            None => Principal::User(self.id),
+        }
+    }
+
+    pub fn is_user(&self) -> bool {
+        matches!(self.principal(), Principal::User(_))
+    }
+
+    /// The user in person. API keys act for a user too, but only within their
+    /// scopes, which never cover managing the account.
+    pub fn is_account_holder(&self) -> bool {
+        self.is_user() && self.scopes.is_none()
+    }
 }
 
 impl FromRequest for Claims{
@@ -32,43 +95,114 @@ impl FromRequest for Claims{
     }
 }
 
-pub fn encode_jwt(email: String, id: i32) -> Result<String,jsonwebtoken::errors::Error> {
+pub fn encode_jwt(user: &entities::user_info::Model, sid: Option<i32>) -> Result<String,jsonwebtoken::errors::Error> {
+    encode_user_token(user, TokenType::Access, sid, Duration::minutes(*constants::ACCESS_TOKEN_TTL_MINUTES))
+}
+
+/// Short-lived token handed out after the password step when the account has MFA enabled.
+pub fn encode_mfa_token(user: &entities::user_info::Model) -> Result<String,jsonwebtoken::errors::Error> {
+    encode_user_token(user, TokenType::MfaPending, None, Duration::minutes(*constants::MFA_TOKEN_TTL_MINUTES))
+}
+
+fn encode_user_token(
+    user: &entities::user_info::Model,
+    typ: TokenType,
+    sid: Option<i32>,
+    expire: Duration,
+) -> Result<String,jsonwebtoken::errors::Error> {
     let now = Utc::now();
-    let expire = Duration::hours(24);
 
     let claims = Claims{
         exp: (now+expire).timestamp() as usize,
         iat: now.timestamp() as  usize,
-        email,
-        id,
+        email: user.email.clone(),
+        id: user.id,
+        jti: Uuid::new_v4().to_string(),
+        ver: user.token_version,
+        role: user.role.parse().unwrap_or_default(),
+        scopes: None,
+        typ,
+        sid,
+        sub: format!("user:{}", user.id),
+        aud: Some(constants::JWT_AUDIENCE.clone()),
+        nbf: Some(now.timestamp() as usize),
+    };
+
+    KEYS.encode(&claims)
+}
+
+/// Access token for an OAuth2 client. It carries no user, only the granted scopes.
+pub fn encode_client_jwt(
+    client: &entities::oauth_client::Model,
+    scopes: Vec<Permission>,
+) -> Result<String,jsonwebtoken::errors::Error> {
+    let now = Utc::now();
+
+    let claims = Claims{
+        exp: (now+Duration::minutes(*constants::ACCESS_TOKEN_TTL_MINUTES)).timestamp() as usize,
+        iat: now.timestamp() as usize,
+        email: String::new(),
+        id: 0,
+        jti: Uuid::new_v4().to_string(),
+        ver: 0,
+        role: Role::default(),
+        scopes: Some(scopes),
+        typ: TokenType::Access,
+        sid: None,
+        sub: format!("client:{}", client.client_id),
+        aud: Some(constants::JWT_AUDIENCE.clone()),
+        nbf: Some(now.timestamp() as usize),
     };
 
-    let secret = (*constants::SECRET).clone();
+    KEYS.encode(&claims)
+}
+
+/// The token of an `Authorization: Bearer <token>` header. The scheme is
+/// case-insensitive, RFC 7235 section 2.1.
+pub fn bearer_token(header: &HeaderValue) -> Result<&str, ServiceError> {
+    let header = header.to_str().map_err(|_| ServiceError::MalformedToken {
+        error_message: "Authorization header is not valid ASCII".to_owned(),
+    })?;
 
-    encode(&Header::default(), &claims,&EncodingKey::from_secret(secret.as_ref()))
+    let (scheme, token) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return Err(ServiceError::InvalidAuthScheme {
+            error_message: "Only Bearer tokens are accepted".to_owned(),
+        });
+    }
+
+    let token = token.trim();
+    if token.is_empty() || token.contains(' ') {
+        return Err(ServiceError::MalformedToken {
+            error_message: "Token is malformed".to_owned(),
+        });
+    }
+
+    Ok(token)
 }
 
 pub fn decode_jwt(jwt: String) -> Result<TokenData<Claims>,jsonwebtoken::errors::Error> {
-    let secret = (*constants::SECRET).clone();
-    let claim_data: Result<TokenData<Claims>, jsonwebtoken::errors::Error> = decode(
-        &jwt, 
-        &DecodingKey::from_secret(secret.as_ref()), 
-        &Validation::default()
-    );
-
-    claim_data
+    KEYS.decode(&jwt)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::test_support;
+
+    fn test_user(email: &str, id: i32) -> entities::user_info::Model {
+        entities::user_info::Model {
+            email: email.to_string(),
+            ..test_support::user(id)
+        }
+    }
 
     #[test]
     fn test_encode_jwt_success() {
         let email = "test@example.com".to_string();
         let id = 123;
         
-        let result = encode_jwt(email.clone(), id);
+        let result = encode_jwt(&test_user(&email, id), None);
         
         assert!(result.is_ok());
         let token = result.unwrap();
@@ -81,7 +215,7 @@ mod tests {
         let id = 123;
         
         // First encode a JWT
-        let token = encode_jwt(email.clone(), id).unwrap();
+        let token = encode_jwt(&test_user(&email, id), None).unwrap();
         
         // Then decode it
         let result = decode_jwt(token);
@@ -109,6 +243,15 @@ mod tests {
             iat: now.timestamp() as usize,
             email: "test@example.com".to_string(),
             id: 456,
+            jti: Uuid::new_v4().to_string(),
+            ver: 0,
+            role: Role::Reader,
+            scopes: None,
+            typ: TokenType::Access,
+            sid: None,
+            sub: "user:456".to_string(),
+            aud: None,
+            nbf: None,
         };
 
         assert_eq!(claims.email, "test@example.com");
@@ -116,6 +259,152 @@ mod tests {
         assert!(claims.exp > claims.iat);
     }
 
+    #[test]
+    fn test_encode_jwt_uses_access_token_ttl() {
+        let token = encode_jwt(&test_user("ttl@example.com", 7), None).unwrap();
+        let claims = decode_jwt(token).unwrap().claims;
+
+        assert_eq!(
+            (claims.exp - claims.iat) as i64,
+            *constants::ACCESS_TOKEN_TTL_MINUTES * 60
+        );
+    }
+
+    #[test]
+    fn test_encode_jwt_carries_jti_and_token_version() {
+        let mut user = test_user("ver@example.com", 8);
+        user.token_version = 3;
+
+        let first = decode_jwt(encode_jwt(&user, None).unwrap()).unwrap().claims;
+        let second = decode_jwt(encode_jwt(&user, None).unwrap()).unwrap().claims;
+
+        assert_eq!(first.ver, 3);
+        assert_ne!(first.jti, second.jti);
+    }
+
+    #[test]
+    fn test_encode_jwt_carries_role() {
+        let mut user = test_user("admin@example.com", 9);
+        user.role = "admin".to_string();
+
+        let claims = decode_jwt(encode_jwt(&user, None).unwrap()).unwrap().claims;
+
+        assert_eq!(claims.role, Role::Admin);
+    }
+
+    #[test]
+    fn test_encode_jwt_carries_session_id() {
+        let user = test_user("session@example.com", 11);
+
+        let with_session = decode_jwt(encode_jwt(&user, Some(5)).unwrap()).unwrap().claims;
+        let without_session = decode_jwt(encode_jwt(&user, None).unwrap()).unwrap().claims;
+
+        assert_eq!(with_session.sid, Some(5));
+        assert_eq!(without_session.sid, None);
+    }
+
+    #[test]
+    fn test_mfa_token_is_not_an_access_token() {
+        let user = test_user("mfa@example.com", 10);
+
+        let access = decode_jwt(encode_jwt(&user, None).unwrap()).unwrap().claims;
+        let pending = decode_jwt(encode_mfa_token(&user).unwrap()).unwrap().claims;
+
+        assert_eq!(access.typ, TokenType::Access);
+        assert_eq!(pending.typ, TokenType::MfaPending);
+        assert_eq!(
+            (pending.exp - pending.iat) as i64,
+            *constants::MFA_TOKEN_TTL_MINUTES * 60
+        );
+    }
+
+    #[test]
+    fn test_principal_kinds() {
+        let user = decode_jwt(encode_jwt(&test_user("user@example.com", 12), None).unwrap())
+            .unwrap()
+            .claims;
+        assert_eq!(user.principal(), Principal::User(12));
+        assert!(user.is_account_holder());
+
+        let client = entities::oauth_client::Model {
+            id: 1,
+            client_id: "rsc_ingest".to_string(),
+            name: "ingest".to_string(),
+            secret_hash: String::new(),
+            scopes: "block:write".to_string(),
+            revoked_at: None,
+            created_at: Utc::now().naive_utc(),
+        };
+        let claims = decode_jwt(encode_client_jwt(&client, vec![Permission::BlockWrite]).unwrap())
+            .unwrap()
+            .claims;
+
+        assert_eq!(claims.principal(), Principal::Client("rsc_ingest"));
+        assert!(!claims.is_user());
+        assert!(!claims.is_account_holder());
+        assert_eq!(claims.scopes, Some(vec![Permission::BlockWrite]));
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let token = |value: &[u8]| {
+            bearer_token(&HeaderValue::from_bytes(value).unwrap())
+                .map(str::to_owned)
+                .map_err(|err| err.code())
+        };
+
+        assert_eq!(token(b"Bearer abc.def.ghi"), Ok("abc.def.ghi".to_string()));
+        assert_eq!(token(b"bearer abc.def.ghi"), Ok("abc.def.ghi".to_string()));
+        assert_eq!(token(b"Basic dXNlcjpwYXNz"), Err("invalid_auth_scheme"));
+        assert_eq!(token(b"abc.def.ghi"), Err("invalid_auth_scheme"));
+        assert_eq!(token(b"Bearer "), Err("malformed_token"));
+        assert_eq!(token(b"Bearer abc def"), Err("malformed_token"));
+        assert_eq!(token(b"Bearer \xff"), Err("malformed_token"));
+    }
+
+    #[test]
+    fn test_decode_rejects_expired_early_and_foreign_tokens() {
+        let user = test_user("claims@example.com", 13);
+        let valid = decode_jwt(encode_jwt(&user, None).unwrap()).unwrap().claims;
+        let now = Utc::now().timestamp() as usize;
+        let rejected = |claims: Claims| {
+            let err = decode_jwt(KEYS.encode(&claims).unwrap()).err().unwrap();
+            ServiceError::from(err).code()
+        };
+
+        let expired = Claims {
+            exp: now - 3600,
+            iat: now - 7200,
+            nbf: None,
+            ..valid.clone()
+        };
+        assert_eq!(rejected(expired), "token_expired");
+
+        let early = Claims {
+            nbf: Some(now + 3600),
+            ..valid.clone()
+        };
+        assert_eq!(rejected(early), "token_not_yet_valid");
+
+        let foreign = Claims {
+            aud: Some("another_service".to_string()),
+            ..valid.clone()
+        };
+        assert_eq!(rejected(foreign), "invalid_audience");
+
+        // an audience must be named, leaving it out would skip the check above
+        assert_eq!(valid.aud.as_deref(), Some(constants::JWT_AUDIENCE.as_str()));
+        let without_audience = Claims {
+            aud: None,
+            ..valid.clone()
+        };
+        assert_eq!(rejected(without_audience), "invalid_token");
+
+        // `nbf` stays optional
+        let without_nbf = Claims { nbf: None, ..valid };
+        assert!(decode_jwt(KEYS.encode(&without_nbf).unwrap()).is_ok());
+    }
+
     #[test]
     fn test_jwt_round_trip_with_different_users() {
         let test_cases = vec![
@@ -125,7 +414,7 @@ mod tests {
         ];
 
         for (email, id) in test_cases {
-            let token = encode_jwt(email.to_string(), id).unwrap();
+            let token = encode_jwt(&test_user(email, id), None).unwrap();
             let decoded = decode_jwt(token).unwrap();
             
             assert_eq!(decoded.claims.email, email);
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QuerySelect, Set, TransactionTrait,
+};
+
+use entities::user_info;
+
+use super::app_state::AppState;
+use super::constants;
+use crate::error::ServiceError;
+
+/// Delay before the next attempt once `failures` reached `threshold`, doubling per
+/// further failure and capped at the lockout duration.
+pub fn backoff(failures: i32, threshold: i32) -> Option<Duration> {
+    if failures < threshold {
+        return None;
+    }
+
+    let lockout = Duration::minutes(*constants::LOGIN_LOCKOUT_MINUTES);
+    let exponent = (failures - threshold).min(30) as u32;
+    let delay = Duration::seconds(*constants::LOGIN_BACKOFF_BASE_SECONDS * 2_i64.pow(exponent));
+
+    Some(delay.min(lockout))
+}
+
+/// How long an account stays locked after its `failures`-th consecutive failed login.
+pub fn account_lock_duration(failures: i32) -> Option<Duration> {
+    if failures >= *constants::LOGIN_LOCKOUT_THRESHOLD {
+        return Some(Duration::minutes(*constants::LOGIN_LOCKOUT_MINUTES));
+    }
+
+    backoff(failures, *constants::LOGIN_BACKOFF_THRESHOLD)
+}
+
+/// Whole seconds until `until`, rounded up so clients never retry too early.
+pub fn retry_after_seconds(until: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    ((until - now).num_milliseconds() + 999) / 1000
+}
+
+struct IpFailures {
+    count: i32,
+    last_failure: DateTime<Utc>,
+}
+
+/// Failed logins per client IP. Kept in memory: it only has to slow down a
+/// guessing client, and a restart merely gives it a fresh budget.
+#[derive(Default)]
+pub struct LoginThrottle {
+    failures: RwLock<HashMap<IpAddr, IpFailures>>,
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// When `ip` may try again, if it is currently throttled.
+    pub fn blocked_until(&self, ip: IpAddr) -> Option<DateTime<Utc>> {
+        let failures = self.failures.read().unwrap();
+        let entry = failures.get(&ip)?;
+        let until = entry.last_failure + backoff(entry.count, *constants::LOGIN_IP_BACKOFF_THRESHOLD)?;
+
+        (until > Utc::now()).then_some(until)
+    }
+
+    pub fn record_failure(&self, ip: IpAddr) {
+        let now = Utc::now();
+        let window = Duration::minutes(*constants::LOGIN_LOCKOUT_MINUTES);
+        let mut failures = self.failures.write().unwrap();
+
+        // quiet addresses start over
+        failures.retain(|_, entry| now - entry.last_failure < window);
+
+        let entry = failures.entry(ip).or_insert(IpFailures {
+            count: 0,
+            last_failure: now,
+        });
+        entry.count += 1;
+        entry.last_failure = now;
+    }
+}
+
+fn too_many_attempts(until: DateTime<Utc>) -> ServiceError {
+    ServiceError::TooManyRequests {
+        error_message: "Too many failed login attempts".to_owned(),
+        retry_after_seconds: retry_after_seconds(until, Utc::now()),
+    }
+}
+
+/// Rejects the attempt while the client IP or the account is backing off.
+/// Login and every check of a second factor go through here.
+pub fn check_attempt(
+    app_state: &AppState,
+    client_ip: Option<IpAddr>,
+    user_data: Option<&user_info::Model>,
+) -> Result<(), ServiceError> {
+    if let Some(until) = client_ip.and_then(|ip| app_state.login_throttle.blocked_until(ip)) {
+        return Err(too_many_attempts(until));
+    }
+
+    if let Some(until) = user_data
+        .and_then(|user| user.locked_until)
+        .filter(|until| *until > Utc::now())
+    {
+        return Err(too_many_attempts(until));
+    }
+
+    Ok(())
+}
+
+pub async fn record_failed_attempt(
+    app_state: &AppState,
+    client_ip: Option<IpAddr>,
+    user_data: Option<&user_info::Model>,
+) -> Result<(), ServiceError> {
+    if let Some(ip) = client_ip {
+        app_state.login_throttle.record_failure(ip);
+    }
+
+    if let Some(user_data) = user_data {
+        let txn = app_state.db.begin().await?;
+
+        // concurrent guesses queue up on the row lock, so each one counts on top of the last
+        let stored = user_info::Entity::find_by_id(user_data.id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?;
+
+        if let Some(stored) = stored {
+            let failed_logins = stored.failed_logins + 1;
+            let locked_until = account_lock_duration(failed_logins)
+                .map(|duration| Utc::now() + duration);
+
+            user_info::Entity::update_many()
+                .col_expr(user_info::Column::FailedLogins, Expr::value(failed_logins))
+                .col_expr(user_info::Column::LockedUntil, Expr::value(locked_until))
+                .filter(user_info::Column::Id.eq(user_data.id))
+                .exec(&txn)
+                .await?;
+        }
+
+        txn.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Clears the failure count after a successful attempt.
+pub async fn reset_failed_attempts(
+    app_state: &AppState,
+    user_data: user_info::Model,
+) -> Result<user_info::Model, ServiceError> {
+    if user_data.failed_logins == 0 && user_data.locked_until.is_none() {
+        return Ok(user_data);
+    }
+
+    let mut user_model = user_data.into_active_model();
+    user_model.failed_logins = Set(0);
+    user_model.locked_until = Set(None);
+
+    user_model
+        .update(&app_state.db)
+        .await
+        .map_err(ServiceError::internal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_is_capped() {
+        assert_eq!(backoff(2, 3), None);
+        assert_eq!(backoff(3, 3), Some(Duration::seconds(*constants::LOGIN_BACKOFF_BASE_SECONDS)));
+        assert_eq!(backoff(5, 3), Some(Duration::seconds(*constants::LOGIN_BACKOFF_BASE_SECONDS * 4)));
+        assert_eq!(
+            backoff(1000, 3),
+            Some(Duration::minutes(*constants::LOGIN_LOCKOUT_MINUTES))
+        );
+    }
+
+    #[test]
+    fn test_account_locks_after_threshold() {
+        assert_eq!(account_lock_duration(1), None);
+        assert_eq!(
+            account_lock_duration(*constants::LOGIN_LOCKOUT_THRESHOLD),
+            Some(Duration::minutes(*constants::LOGIN_LOCKOUT_MINUTES))
+        );
+    }
+
+    #[test]
+    fn test_ip_is_throttled_after_threshold() {
+        let throttle = LoginThrottle::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        for _ in 1..*constants::LOGIN_IP_BACKOFF_THRESHOLD {
+            throttle.record_failure(ip);
+        }
+        assert!(throttle.blocked_until(ip).is_none());
+
+        throttle.record_failure(ip);
+        assert!(throttle.blocked_until(ip).is_some());
+        assert!(throttle.blocked_until(other).is_none());
+    }
+
+    #[test]
+    fn test_retry_after_rounds_up() {
+        let now = Utc::now();
+
+        assert_eq!(retry_after_seconds(now + Duration::milliseconds(1500), now), 2);
+        assert_eq!(retry_after_seconds(now + Duration::seconds(30), now), 30);
+    }
+}
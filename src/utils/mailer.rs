@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::PathBuf;
+
+use actix_web::web;
+use async_trait::async_trait;
+use chrono::Utc;
+use derive_more::Display;
+use lettre::message::Mailbox;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use uuid::Uuid;
+
+use super::constants;
+
+#[derive(Debug, Display)]
+pub enum MailError {
+    #[display(fmt = "Invalid address: {_0}")]
+    Address(String),
+
+    #[display(fmt = "Mail configuration error: {_0}")]
+    Config(String),
+
+    #[display(fmt = "Could not deliver mail: {_0}")]
+    Transport(String),
+}
+
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Mail {
+    fn to_message(&self, from: &Mailbox) -> Result<Message, MailError> {
+        let to = self
+            .to
+            .parse::<Mailbox>()
+            .map_err(|err| MailError::Address(err.to_string()))?;
+
+        Message::builder()
+            .from(from.clone())
+            .to(to)
+            .subject(&self.subject)
+            .body(self.body.clone())
+            .map_err(|err| MailError::Address(err.to_string()))
+    }
+}
+
+/// Outgoing mail transport, picked at startup from `MAIL_TRANSPORT`.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), MailError>;
+}
+
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    pub fn new(url: &str, from: Mailbox) -> Result<Self, MailError> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+            .map_err(|err| MailError::Config(err.to_string()))?
+            .build();
+
+        Ok(SmtpMailer { from, transport })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        self.transport
+            .send(mail.to_message(&self.from)?)
+            .await
+            .map_err(|err| MailError::Transport(err.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// Local stand-in that writes each message as an `.eml` file, or to stdout
+/// when no directory is given.
+pub struct FileMailer {
+    from: Mailbox,
+    dir: Option<PathBuf>,
+}
+
+impl FileMailer {
+    pub fn new(from: Mailbox, dir: Option<PathBuf>) -> Self {
+        FileMailer { from, dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> Result<(), MailError> {
+        let formatted = mail.to_message(&self.from)?.formatted();
+
+        match &self.dir {
+            Some(dir) => {
+                let path = dir.join(format!(
+                    "{}-{}.eml",
+                    Utc::now().format("%Y%m%dT%H%M%S"),
+                    Uuid::new_v4().simple()
+                ));
+
+                let dir = dir.clone();
+
+                web::block(move || fs::create_dir_all(dir).and_then(|_| fs::write(path, formatted)))
+                    .await
+                    .map_err(|err| MailError::Transport(err.to_string()))?
+                    .map_err(|err| MailError::Transport(err.to_string()))
+            }
+            None => {
+                println!("{}", String::from_utf8_lossy(&formatted));
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn from_config() -> Result<Box<dyn Mailer>, MailError> {
+    let from = constants::MAIL_FROM
+        .parse::<Mailbox>()
+        .map_err(|err| MailError::Config(format!("MAIL_FROM: {}", err)))?;
+
+    // stdout would put live verification and reset links into the process logs
+    let transport = constants::MAIL_TRANSPORT.as_deref().unwrap_or_else(|| {
+        log::warn!(
+            "MAIL_TRANSPORT is not set, writing mail to {} instead of sending it",
+            constants::MAIL_FILE_DIR.as_str()
+        );
+        "file"
+    });
+
+    match transport {
+        "smtp" => {
+            let url = constants::SMTP_URL
+                .as_deref()
+                .ok_or(MailError::Config("SMTP_URL is required for smtp".to_string()))?;
+            Ok(Box::new(SmtpMailer::new(url, from)?))
+        }
+        "file" => Ok(Box::new(FileMailer::new(
+            from,
+            Some(PathBuf::from(constants::MAIL_FILE_DIR.as_str())),
+        ))),
+        "stdout" => Ok(Box::new(FileMailer::new(from, None))),
+        other => Err(MailError::Config(format!("unknown MAIL_TRANSPORT {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from() -> Mailbox {
+        "rust_server <noreply@example.com>".parse().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn test_file_mailer_writes_eml() {
+        let dir = std::env::temp_dir().join(format!("mailer-{}", Uuid::new_v4().simple()));
+        let mailer = FileMailer::new(from(), Some(dir.clone()));
+
+        mailer
+            .send(Mail {
+                to: "user@example.com".to_string(),
+                subject: "Verify your email".to_string(),
+                body: "hello".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let files = fs::read_dir(&dir).unwrap().collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+
+        let contents = fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(contents.contains("Subject: Verify your email"));
+        assert!(contents.contains("To: user@example.com"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn test_invalid_recipient_is_rejected() {
+        let mailer = FileMailer::new(from(), None);
+
+        let result = mailer
+            .send(Mail {
+                to: "not an address".to_string(),
+                subject: "Hi".to_string(),
+                body: String::new(),
+            })
+            .await;
+
+        assert!(matches!(result, Err(MailError::Address(_))));
+    }
+}
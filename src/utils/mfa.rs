@@ -0,0 +1,308 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use sea_orm::sea_query::{Condition, Expr};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use entities::{mfa_recovery_code, user_mfa};
+
+use super::constants;
+
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+const MIN_SECRET_KEY_LEN: usize = 32;
+const SEALED_PREFIX: &str = "v1.";
+
+/// Encrypts TOTP secrets before they are stored. Unlike a password a secret has
+/// to be read back to check codes, so it cannot be hashed; with this a copy of
+/// the `user_mfa` table alone is not enough to produce codes.
+pub struct SecretCipher {
+    cipher: Aes256Gcm,
+}
+
+impl SecretCipher {
+    pub fn new(key: &[u8]) -> Result<Self, String> {
+        if key.len() < MIN_SECRET_KEY_LEN {
+            return Err(format!("MFA_SECRET_KEY must be at least {} bytes", MIN_SECRET_KEY_LEN));
+        }
+
+        let key = Sha256::digest(key);
+
+        Ok(SecretCipher {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        })
+    }
+
+    pub fn from_config() -> Result<Self, String> {
+        let key = constants::MFA_SECRET_KEY
+            .as_deref()
+            .ok_or("MFA_SECRET_KEY is required".to_owned())?;
+
+        SecretCipher::new(key.as_bytes())
+    }
+
+    /// `v1.` followed by the nonce and ciphertext, base64url encoded.
+    pub fn seal(&self, secret: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .expect("Can't encrypt the mfa secret");
+
+        format!("{}{}", SEALED_PREFIX, URL_SAFE_NO_PAD.encode([nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    /// Plain secret of a stored value. Rows enrolled before secrets were
+    /// encrypted are returned as they are, see `is_sealed`.
+    pub fn open(&self, stored: &str) -> Result<String, String> {
+        let Some(sealed) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_owned());
+        };
+
+        let bytes = URL_SAFE_NO_PAD
+            .decode(sealed)
+            .map_err(|err| err.to_string())?;
+        if bytes.len() < 12 {
+            return Err("Sealed mfa secret is too short".to_owned());
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(12);
+        let secret = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Can't decrypt the mfa secret".to_owned())?;
+
+        String::from_utf8(secret).map_err(|err| err.to_string())
+    }
+
+    pub fn is_sealed(stored: &str) -> bool {
+        stored.starts_with(SEALED_PREFIX)
+    }
+}
+
+/// Fresh 160-bit TOTP secret, base32 encoded as authenticator apps expect it.
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// RFC 6238 generator for `secret`, labelled with the account for the otpauth URI.
+pub fn totp(secret: &str, account_name: &str) -> Result<TOTP, String> {
+    let secret = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|err| err.to_string())?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        0,
+        TOTP_STEP,
+        secret,
+        Some(constants::MFA_ISSUER.clone()),
+        account_name.to_owned(),
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// Time step `code` belongs to, allowing one step of clock drift either way.
+pub fn matching_step(totp: &TOTP, code: &str, now: u64) -> Option<u64> {
+    let current = now / TOTP_STEP;
+
+    [current, current.saturating_sub(1), current + 1]
+        .into_iter()
+        .find(|step| totp.check(code.trim(), step * TOTP_STEP))
+}
+
+/// One-time recovery codes in `xxxxx-xxxxx` form, shown to the user once.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let random = Uuid::new_v4().simple().to_string();
+            format!("{}-{}", &random[..5], &random[5..10])
+        })
+        .collect()
+}
+
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized = code
+        .trim()
+        .replace('-', "")
+        .to_ascii_lowercase();
+
+    sha256::digest(normalized)
+}
+
+pub async fn find_enabled(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Option<user_mfa::Model>, DbErr> {
+    user_mfa::Entity::find()
+        .filter(user_mfa::Column::UserId.eq(user_id))
+        .filter(user_mfa::Column::EnabledAt.is_not_null())
+        .one(db)
+        .await
+}
+
+/// Replaces any previous recovery codes of the user and returns the new plain codes.
+pub async fn replace_recovery_codes(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<String>, DbErr> {
+    mfa_recovery_code::Entity::delete_many()
+        .filter(mfa_recovery_code::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let now = Utc::now().naive_utc();
+    let codes = generate_recovery_codes();
+
+    mfa_recovery_code::Entity::insert_many(codes.iter().map(|code| {
+        mfa_recovery_code::ActiveModel {
+            user_id: Set(user_id),
+            code_hash: Set(hash_recovery_code(code)),
+            used_at: Set(None),
+            created_at: Set(now),
+            ..Default::default()
+        }
+    }))
+    .exec(db)
+    .await?;
+
+    Ok(codes)
+}
+
+/// Records `step` as used; `false` if that code (or a later one) was already accepted.
+async fn consume_step(db: &DatabaseConnection, mfa: &user_mfa::Model, step: u64) -> Result<bool, DbErr> {
+    let result = user_mfa::Entity::update_many()
+        .col_expr(user_mfa::Column::LastUsedStep, Expr::value(step as i64))
+        .filter(user_mfa::Column::Id.eq(mfa.id))
+        .filter(
+            Condition::any()
+                .add(user_mfa::Column::LastUsedStep.is_null())
+                .add(user_mfa::Column::LastUsedStep.lt(step as i64)),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Checks a TOTP code or, failing that, burns a matching recovery code.
+pub async fn verify_second_factor(
+    db: &DatabaseConnection,
+    secrets: &SecretCipher,
+    mfa: &user_mfa::Model,
+    account_name: &str,
+    code: &str,
+) -> Result<bool, DbErr> {
+    let secret = secrets.open(&mfa.secret).map_err(DbErr::Custom)?;
+    let totp = totp(&secret, account_name).map_err(DbErr::Custom)?;
+    let now = Utc::now().timestamp() as u64;
+
+    if let Some(step) = matching_step(&totp, code, now) {
+        let consumed = consume_step(db, mfa, step).await?;
+
+        // secrets stored before encryption are sealed on their next use
+        if consumed && !SecretCipher::is_sealed(&mfa.secret) {
+            user_mfa::Entity::update_many()
+                .col_expr(user_mfa::Column::Secret, Expr::value(secrets.seal(&secret)))
+                .filter(user_mfa::Column::Id.eq(mfa.id))
+                .exec(db)
+                .await?;
+        }
+
+        return Ok(consumed);
+    }
+
+    let result = mfa_recovery_code::Entity::update_many()
+        .col_expr(
+            mfa_recovery_code::Column::UsedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(mfa_recovery_code::Column::UserId.eq(mfa.user_id))
+        .filter(mfa_recovery_code::Column::CodeHash.eq(hash_recovery_code(code)))
+        .filter(mfa_recovery_code::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// Marks a pending enrollment as enabled once the user proved they can produce codes.
+pub async fn enable(db: &DatabaseConnection, mfa: user_mfa::Model, step: u64) -> Result<user_mfa::Model, DbErr> {
+    let mut mfa_model: user_mfa::ActiveModel = mfa.into();
+    mfa_model.enabled_at = Set(Some(Utc::now().naive_utc()));
+    mfa_model.last_used_step = Set(Some(step as i64));
+
+    mfa_model.update(db).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_secret_builds_totp() {
+        let secret = generate_secret();
+        let totp = totp(&secret, "user@example.com").unwrap();
+
+        assert_eq!(totp.get_secret_base32(), secret);
+        assert!(totp.get_url().starts_with("otpauth://totp/"));
+        assert!(totp.get_url().contains("secret="));
+    }
+
+    #[test]
+    fn test_matching_step_allows_one_step_of_drift() {
+        let totp = totp(&generate_secret(), "user@example.com").unwrap();
+        let now = 1_700_000_000;
+        let step = now / TOTP_STEP;
+
+        let previous = totp.generate((step - 1) * TOTP_STEP);
+        let current = totp.generate(now);
+        let stale = totp.generate((step - 2) * TOTP_STEP);
+
+        assert_eq!(matching_step(&totp, &current, now), Some(step));
+        assert_eq!(matching_step(&totp, &previous, now), Some(step - 1));
+        assert_eq!(matching_step(&totp, &stale, now), None);
+        assert_eq!(matching_step(&totp, "12345", now), None);
+    }
+
+    #[test]
+    fn test_sealed_secret_opens_only_with_its_key() {
+        let secrets = SecretCipher::new(&[7; 32]).unwrap();
+        let secret = generate_secret();
+
+        let sealed = secrets.seal(&secret);
+        assert!(SecretCipher::is_sealed(&sealed));
+        assert!(!sealed.contains(&secret));
+        assert_ne!(secrets.seal(&secret), sealed);
+        assert_eq!(secrets.open(&sealed).unwrap(), secret);
+
+        let other = SecretCipher::new(&[8; 32]).unwrap();
+        assert!(other.open(&sealed).is_err());
+
+        // rows enrolled before secrets were encrypted
+        assert_eq!(secrets.open(&secret).unwrap(), secret);
+        assert!(SecretCipher::new(&[7; 31]).is_err());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|code| code.len() == 11 && &code[5..6] == "-"));
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&codes[0].replace('-', "").to_uppercase())
+        );
+        assert_ne!(hash_recovery_code(&codes[0]), hash_recovery_code(&codes[1]));
+    }
+}
@@ -1,5 +1,32 @@
 pub mod constants;
+pub mod action_token;
+pub mod api_key;
 pub mod api_response;
 pub mod app_state;
+pub mod audit;
+pub mod auth_cookie;
+pub mod avatar;
+pub mod blob_store;
+pub mod eth;
 pub mod jwt;
-pub mod thread_pool;
\ No newline at end of file
+pub mod jwt_keys;
+pub mod login_throttle;
+pub mod mailer;
+pub mod mfa;
+pub mod nonce;
+pub mod oauth_client;
+pub mod password;
+pub mod personal_data;
+pub mod profile;
+pub mod refresh_token;
+pub mod request_body;
+pub mod revocation;
+pub mod roles;
+pub mod session;
+pub mod siwe;
+#[cfg(test)]
+pub mod test_support;
+pub mod thread_pool;
+pub mod trusted_issuers;
+pub mod validation;
+pub mod wallet;
\ No newline at end of file
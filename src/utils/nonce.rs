@@ -0,0 +1,42 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use uuid::Uuid;
+
+use entities::auth_nonce;
+
+use super::constants;
+
+/// Stores a fresh single-use nonce and returns it with its expiry.
+pub async fn issue_nonce(db: &DatabaseConnection) -> Result<(String, NaiveDateTime), DbErr> {
+    let now = Utc::now().naive_utc();
+    let nonce = Uuid::new_v4().simple().to_string();
+    let expires_at = now + Duration::minutes(*constants::NONCE_TTL_MINUTES);
+
+    auth_nonce::ActiveModel {
+        nonce: Set(nonce.clone()),
+        expires_at: Set(expires_at),
+        used_at: Set(None),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok((nonce, expires_at))
+}
+
+/// Marks `nonce` as used; `false` if it is unknown, expired or was already used.
+pub async fn consume_nonce(db: &DatabaseConnection, nonce: &str) -> Result<bool, DbErr> {
+    let now = Utc::now().naive_utc();
+
+    let result = auth_nonce::Entity::update_many()
+        .col_expr(auth_nonce::Column::UsedAt, Expr::value(now))
+        .filter(auth_nonce::Column::Nonce.eq(nonce))
+        .filter(auth_nonce::Column::UsedAt.is_null())
+        .filter(auth_nonce::Column::ExpiresAt.gt(now))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
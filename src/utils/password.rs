@@ -0,0 +1,165 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use lazy_static::lazy_static;
+
+use super::auth_cookie::constant_time_eq;
+use super::constants;
+
+/// Stored in place of a hash when an admin forces a reset. It is neither a PHC
+/// string nor a sha256 digest, so nothing verifies against it, and unlike an
+/// empty hash it does not read as "this account never had a password".
+pub const RESET_REQUIRED: &str = "!reset-required";
+
+lazy_static! {
+    /// Hash with the current parameters, checked when there is no account, see `verify_dummy`.
+    static ref DUMMY_HASH: String = hash_password("dummy password").expect("Can't hash the dummy password");
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordCheck {
+    Valid,
+    /// Correct password, but the stored hash is legacy sha256 or uses outdated parameters.
+    ValidNeedsRehash,
+    Invalid,
+}
+
+fn argon2() -> Result<Argon2<'static>, argon2::Error> {
+    let params = Params::new(
+        *constants::ARGON2_MEMORY_KIB,
+        *constants::ARGON2_ITERATIONS,
+        *constants::ARGON2_PARALLELISM,
+        None,
+    )?;
+
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Hashes `password` with Argon2id into a PHC string.
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(argon2()?
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Only accounts created through SSO have never had a password.
+pub fn never_set(stored_hash: &str) -> bool {
+    stored_hash.is_empty()
+}
+
+pub fn verify_password(password: &str, stored_hash: &str) -> PasswordCheck {
+    let parsed_hash = match PasswordHash::new(stored_hash) {
+        Ok(parsed_hash) => parsed_hash,
+        // anything that is not a PHC string is an unsalted sha256 digest from before argon2
+        Err(_) => {
+            return if constant_time_eq(sha256::digest(password).as_bytes(), stored_hash.as_bytes()) {
+                PasswordCheck::ValidNeedsRehash
+            } else {
+                PasswordCheck::Invalid
+            }
+        }
+    };
+
+    let argon2 = match argon2() {
+        Ok(argon2) => argon2,
+        Err(_) => return PasswordCheck::Invalid,
+    };
+
+    if argon2
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_err()
+    {
+        return PasswordCheck::Invalid;
+    }
+
+    let current_params = Params::try_from(&parsed_hash).ok();
+    let up_to_date = parsed_hash.algorithm == Algorithm::Argon2id.ident()
+        && current_params.as_ref().is_some_and(|params| {
+            params.m_cost() == *constants::ARGON2_MEMORY_KIB
+                && params.t_cost() == *constants::ARGON2_ITERATIONS
+                && params.p_cost() == *constants::ARGON2_PARALLELISM
+        });
+
+    if up_to_date {
+        PasswordCheck::Valid
+    } else {
+        PasswordCheck::ValidNeedsRehash
+    }
+}
+
+/// Takes as long as checking a real password, so a login for an unknown
+/// email cannot be told apart by its response time.
+pub fn verify_dummy(password: &str) {
+    verify_password(password, &DUMMY_HASH);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_password_is_salted_argon2id() {
+        let first = hash_password("hunter2").unwrap();
+        let second = hash_password("hunter2").unwrap();
+
+        assert!(first.starts_with("$argon2id$"));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_verify_password() {
+        let hash = hash_password("hunter2").unwrap();
+
+        assert_eq!(verify_password("hunter2", &hash), PasswordCheck::Valid);
+        assert_eq!(verify_password("hunter3", &hash), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn test_verify_legacy_sha256_password() {
+        let legacy_hash = sha256::digest("hunter2");
+
+        assert_eq!(
+            verify_password("hunter2", &legacy_hash),
+            PasswordCheck::ValidNeedsRehash
+        );
+        assert_eq!(
+            verify_password("hunter3", &legacy_hash),
+            PasswordCheck::Invalid
+        );
+    }
+
+    #[test]
+    fn test_dummy_hash_costs_as_much_as_a_current_hash() {
+        // `Valid` rather than `ValidNeedsRehash` means it uses today's parameters
+        assert_eq!(verify_password("dummy password", &DUMMY_HASH), PasswordCheck::Valid);
+    }
+
+    #[test]
+    fn test_nothing_verifies_after_a_forced_reset() {
+        assert_eq!(verify_password("", RESET_REQUIRED), PasswordCheck::Invalid);
+        assert_eq!(verify_password(RESET_REQUIRED, RESET_REQUIRED), PasswordCheck::Invalid);
+        assert!(!never_set(RESET_REQUIRED));
+        assert!(never_set(""));
+    }
+
+    #[test]
+    fn test_verify_outdated_params_needs_rehash() {
+        let weak = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(8, 1, 1, None).unwrap(),
+        );
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = weak
+            .hash_password("hunter2".as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+
+        assert_eq!(
+            verify_password("hunter2", &hash),
+            PasswordCheck::ValidNeedsRehash
+        );
+    }
+}
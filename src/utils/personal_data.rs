@@ -0,0 +1,423 @@
+use std::time::Duration as StdDuration;
+
+use actix_web::{rt, web};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use serde::Serialize;
+
+use entities::{
+    action_token, api_key, mfa_recovery_code, refresh_token, user_identity, user_info, user_mfa,
+    user_session, user_wallet,
+};
+
+use super::app_state::AppState;
+use super::blob_store::BlobStore;
+use super::revocation::RevocationStore;
+use super::{avatar, constants, eth, session, wallet};
+
+#[derive(Serialize)]
+pub struct ProfileExport {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub age: i32,
+    pub image: String,
+    pub wallet_address: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct WalletExport {
+    pub chain_id: i64,
+    pub address: String,
+    pub label: String,
+    pub verified_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct SessionExport {
+    pub user_agent: String,
+    pub ip_address: String,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct IdentityExport {
+    pub issuer: String,
+    pub subject: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct TransactionExport {
+    pub id: i32,
+    pub block_id: i32,
+    pub tx_hash: String,
+    pub tx_type: i32,
+    pub from_address: String,
+    pub to_address: String,
+    pub tx_memo: String,
+    pub tx_amount: i32,
+    pub tx_fee: i32,
+    pub tx_status: String,
+    pub tx_time: String,
+}
+
+/// Everything we hold about one user. Secrets (password hash, MFA seed, key
+/// hashes) are left out; they are useless to the user and dangerous in a download.
+#[derive(Serialize)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub profile: ProfileExport,
+    pub wallets: Vec<WalletExport>,
+    pub sessions: Vec<SessionExport>,
+    pub identities: Vec<IdentityExport>,
+    pub transactions: Vec<TransactionExport>,
+}
+
+/// How long a link to a stored export stays valid.
+pub const EXPORT_LINK_TTL_MINUTES: i64 = 15;
+
+/// Where a stored export lives. There is one per account, replaced on every
+/// request, so no archive outlives the account.
+pub fn export_key(user_id: i32) -> String {
+    format!("exports/{}/account.json", user_id)
+}
+
+pub async fn export_account(
+    db: &DatabaseConnection,
+    user: &user_info::Model,
+) -> Result<AccountExport, DbErr> {
+    let wallets = wallet::user_wallets(db, user.id)
+        .await?
+        .into_iter()
+        .map(|wallet| WalletExport {
+            chain_id: wallet.chain_id,
+            address: eth::to_checksum_address(&wallet.address).unwrap_or(wallet.address),
+            label: wallet.label,
+            verified_at: wallet.verified_at,
+            created_at: wallet.created_at,
+        })
+        .collect();
+
+    let sessions = user_session::Entity::find()
+        .filter(user_session::Column::UserId.eq(user.id))
+        .order_by_asc(user_session::Column::CreatedAt)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|session| SessionExport {
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            revoked_at: session.revoked_at,
+        })
+        .collect();
+
+    let identities = user_identity::Entity::find()
+        .filter(user_identity::Column::UserId.eq(user.id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|identity| IdentityExport {
+            issuer: identity.issuer,
+            subject: identity.subject,
+            created_at: identity.created_at,
+        })
+        .collect();
+
+    let transactions = wallet::user_transactions(db, user.id)
+        .await?
+        .into_iter()
+        .map(|tx| TransactionExport {
+            id: tx.id,
+            block_id: tx.block_id,
+            tx_hash: tx.tx_hash,
+            tx_type: tx.tx_type,
+            from_address: tx.from_address,
+            to_address: tx.to_address,
+            tx_memo: tx.tx_memo,
+            tx_amount: tx.tx_amount,
+            tx_fee: tx.tx_fee,
+            tx_status: tx.tx_status,
+            tx_time: tx.tx_time,
+        })
+        .collect();
+
+    Ok(AccountExport {
+        exported_at: Utc::now(),
+        profile: ProfileExport {
+            id: user.id,
+            name: user.name.clone(),
+            email: user.email.clone(),
+            email_verified: user.email_verified,
+            age: user.age,
+            image: user.image.clone(),
+            wallet_address: user.wallet_address.clone(),
+            role: user.role.clone(),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        },
+        wallets,
+        sessions,
+        identities,
+        transactions,
+    })
+}
+
+/// When a deleted account gets purged.
+pub fn purge_after(deleted_at: DateTime<Utc>) -> DateTime<Utc> {
+    deleted_at + Duration::days(*constants::ACCOUNT_DELETION_GRACE_DAYS)
+}
+
+/// Marks the account deleted and ends every way of acting as it. The data
+/// stays until the grace period is over, so the deletion can still be undone.
+pub async fn soft_delete(
+    db: &DatabaseConnection,
+    revocations: &RevocationStore,
+    user: user_info::Model,
+) -> Result<user_info::Model, DbErr> {
+    let user_id = user.id;
+    let mut user_model = user.into_active_model();
+    user_model.deleted_at = Set(Some(Utc::now()));
+    let user = user_model.update(db).await?;
+
+    session::end_all_logins(db, revocations, user_id).await?;
+
+    // pending verification and reset links must not revive the account
+    action_token::Entity::delete_many()
+        .filter(action_token::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    Ok(user)
+}
+
+/// Tombstone left for a purged account. The row keeps its id so nothing
+/// that still mentions it can be pointed at a new account.
+pub fn anonymized(user: user_info::Model, now: DateTime<Utc>) -> user_info::ActiveModel {
+    let user_id = user.id;
+    let mut user_model = user.into_active_model();
+
+    user_model.name = Set("Deleted user".to_owned());
+    user_model.email = Set(format!("deleted-{}@deleted.invalid", user_id));
+    user_model.email_verified = Set(false);
+    user_model.age = Set(0);
+    user_model.image = Set(String::new());
+    user_model.password = Set(String::new());
+    user_model.wallet_address = Set(String::new());
+    user_model.failed_logins = Set(0);
+    user_model.locked_until = Set(None);
+    user_model.purged_at = Set(Some(now));
+
+    user_model
+}
+
+/// Removes everything tied to the account and anonymizes what is left of it.
+pub async fn purge_account(
+    db: &DatabaseConnection,
+    blobs: &dyn BlobStore,
+    user: user_info::Model,
+) -> Result<(), DbErr> {
+    let user_id = user.id;
+    let avatar_id = avatar::avatar_id_from_url(user_id, &user.image);
+
+    let txn = db.begin().await?;
+
+    user_wallet::Entity::delete_many()
+        .filter(user_wallet::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    user_session::Entity::delete_many()
+        .filter(user_session::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    user_identity::Entity::delete_many()
+        .filter(user_identity::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    refresh_token::Entity::delete_many()
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    api_key::Entity::delete_many()
+        .filter(api_key::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    user_mfa::Entity::delete_many()
+        .filter(user_mfa::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    mfa_recovery_code::Entity::delete_many()
+        .filter(mfa_recovery_code::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    action_token::Entity::delete_many()
+        .filter(action_token::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+
+    anonymized(user, Utc::now()).update(&txn).await?;
+
+    txn.commit().await?;
+
+    // the rows are gone either way, a leftover file is only wasted space
+    if let Some(avatar_id) = avatar_id {
+        for size in avatar::AVATAR_SIZES {
+            if let Err(err) = blobs.delete(&avatar::avatar_key(user_id, &avatar_id, size)).await {
+                log::warn!("could not delete avatar of purged user {}: {}", user_id, err);
+            }
+        }
+    }
+
+    if let Err(err) = blobs.delete(&export_key(user_id)).await {
+        log::warn!("could not delete the export of purged user {}: {}", user_id, err);
+    }
+
+    Ok(())
+}
+
+/// Outcome of one `purge_due_accounts` run.
+#[derive(Debug, Default, PartialEq)]
+pub struct PurgeReport {
+    pub purged: usize,
+    pub failed: usize,
+}
+
+/// Purges every account whose grace period is over. An account that cannot be
+/// purged is logged and retried next run; it must not hold up the others.
+pub async fn purge_due_accounts(
+    db: &DatabaseConnection,
+    blobs: &dyn BlobStore,
+    now: DateTime<Utc>,
+) -> Result<PurgeReport, DbErr> {
+    let cutoff = now - Duration::days(*constants::ACCOUNT_DELETION_GRACE_DAYS);
+
+    let due = user_info::Entity::find()
+        .filter(user_info::Column::DeletedAt.lte(cutoff))
+        .filter(user_info::Column::PurgedAt.is_null())
+        .all(db)
+        .await?;
+
+    let mut report = PurgeReport::default();
+    for user in due {
+        let user_id = user.id;
+
+        match purge_account(db, blobs, user).await {
+            Ok(()) => report.purged += 1,
+            Err(err) => {
+                log::error!("purging deleted account {} failed: {}", user_id, err);
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Background job started by `main`, runs every `ACCOUNT_PURGE_INTERVAL_MINUTES`.
+pub async fn purge_periodically(app_state: web::Data<AppState>) {
+    let mut interval = rt::time::interval(StdDuration::from_secs(
+        *constants::ACCOUNT_PURGE_INTERVAL_MINUTES * 60,
+    ));
+
+    loop {
+        interval.tick().await;
+
+        match purge_due_accounts(&app_state.db, app_state.blobs.as_ref(), Utc::now()).await {
+            Ok(PurgeReport { purged: 0, failed: 0 }) => {}
+            Ok(PurgeReport { purged, failed }) => {
+                log::info!("purged {} deleted accounts, {} failed", purged, failed)
+            }
+            Err(err) => log::error!("purging deleted accounts failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support;
+    use super::super::blob_store::LocalBlobStore;
+    use sea_orm::{ActiveValue, DatabaseBackend, MockDatabase, MockExecResult};
+
+    fn test_user() -> user_info::Model {
+        user_info::Model {
+            name: "Ada Lovelace".to_string(),
+            age: 36,
+            image: "/avatars/7/abc/256.png".to_string(),
+            email: "ada@example.com".to_string(),
+            password: "$argon2id$v=19$...".to_string(),
+            wallet_address: "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23".to_string(),
+            token_version: 3,
+            failed_logins: 2,
+            email_verified: true,
+            deleted_at: Some(Utc::now()),
+            ..test_support::user(7)
+        }
+    }
+
+    #[test]
+    fn test_anonymized_drops_personal_data() {
+        let now = Utc::now();
+        let user_model = anonymized(test_user(), now);
+
+        assert_eq!(user_model.id, ActiveValue::Unchanged(7));
+        assert_eq!(user_model.name, ActiveValue::Set("Deleted user".to_string()));
+        assert_eq!(user_model.email, ActiveValue::Set("deleted-7@deleted.invalid".to_string()));
+        assert_eq!(user_model.password, ActiveValue::Set(String::new()));
+        assert_eq!(user_model.wallet_address, ActiveValue::Set(String::new()));
+        assert_eq!(user_model.image, ActiveValue::Set(String::new()));
+        assert_eq!(user_model.age, ActiveValue::Set(0));
+        assert_eq!(user_model.purged_at, ActiveValue::Set(Some(now)));
+    }
+
+    #[test]
+    fn test_purge_after_grace_period() {
+        let deleted_at = Utc::now();
+
+        assert_eq!(
+            purge_after(deleted_at) - deleted_at,
+            Duration::days(*constants::ACCOUNT_DELETION_GRACE_DAYS)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_failed_purge_does_not_stop_the_batch() {
+        let done = || MockExecResult {
+            last_insert_id: 0,
+            rows_affected: 1,
+        };
+        let broken = user_info::Model {
+            image: String::new(),
+            ..test_user()
+        };
+        let next = user_info::Model {
+            id: 8,
+            image: String::new(),
+            ..test_user()
+        };
+
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![broken, next.clone()]])
+            .append_exec_errors([DbErr::Custom("lock wait timeout".to_string())])
+            .append_exec_results((0..9).map(|_| done()))
+            .append_query_results([vec![next]])
+            .into_connection();
+        let blobs = LocalBlobStore::new(std::env::temp_dir());
+
+        let report = purge_due_accounts(&db, &blobs, Utc::now()).await.unwrap();
+
+        assert_eq!(report, PurgeReport { purged: 1, failed: 1 });
+    }
+}
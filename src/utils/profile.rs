@@ -0,0 +1,209 @@
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use validator::Validate;
+
+use super::validation::{self, FieldErrors};
+use crate::error::ServiceError;
+
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+const EDITABLE_FIELDS: [&str; 3] = ["name", "age", "image"];
+
+/// The editable part of `user_info`, as returned by `my_info` and patched by `PATCH secure/user`.
+#[derive(Serialize, Deserialize, Validate, Clone, Debug, PartialEq)]
+pub struct Profile {
+    #[validate(custom(function = "validation::name"))]
+    pub name: String,
+    #[validate(custom(function = "validation::age"))]
+    pub age: i32,
+    #[validate(custom(function = "validation::image"))]
+    pub image: String,
+}
+
+impl From<&entities::user_info::Model> for Profile {
+    fn from(user: &entities::user_info::Model) -> Self {
+        Profile {
+            name: user.name.clone(),
+            age: user.age,
+            image: user.image.clone(),
+        }
+    }
+}
+
+/// Strong validator of a profile version. `updated_at` changes on every
+/// profile write, see `next_updated_at`.
+pub fn etag(updated_at: DateTime<Utc>) -> String {
+    format!("\"{:x}\"", updated_at.timestamp_millis())
+}
+
+/// Whether an `If-Match` header value selects the current version. Weak tags
+/// never match, as RFC 9110 requires strong comparison here.
+pub fn if_match(header: &str, current: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == current)
+}
+
+/// `updated_at` for a write. The column has second precision, so a second
+/// write within the same second would otherwise keep the old ETag.
+pub fn next_updated_at(previous: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+    let now = now.duration_trunc(Duration::seconds(1)).unwrap_or(now);
+
+    now.max(previous + Duration::seconds(1))
+}
+
+/// RFC 7396 JSON Merge Patch: objects merge recursively, `null` removes a
+/// member and anything else replaces the target.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+impl Profile {
+    /// Applies a merge patch. Removing `image` clears it; `name` and `age` cannot be removed.
+    pub fn patched(&self, patch: &Value) -> Result<Profile, ServiceError> {
+        let Value::Object(members) = patch else {
+            return Err(validation::failed(FieldErrors::from([(
+                String::new(),
+                "a merge patch for a profile must be a JSON object".to_owned(),
+            )])));
+        };
+
+        let mut errors = members
+            .keys()
+            .filter(|key| !EDITABLE_FIELDS.contains(&key.as_str()))
+            .map(|key| (key.clone(), "is not an editable field".to_owned()))
+            .collect::<FieldErrors>();
+
+        let mut document = serde_json::to_value(self).unwrap_or_default();
+        merge_patch(&mut document, patch);
+
+        let name = match document.get("name") {
+            Some(Value::String(name)) => name.trim().to_owned(),
+            _ => {
+                errors.insert("name".to_owned(), "must be a string".to_owned());
+                String::new()
+            }
+        };
+        let age = match document.get("age").and_then(Value::as_i64) {
+            Some(age) => i32::try_from(age).unwrap_or(i32::MAX),
+            None => {
+                errors.insert("age".to_owned(), "must be an integer".to_owned());
+                0
+            }
+        };
+        let image = match document.get("image") {
+            None => String::new(),
+            Some(Value::String(image)) => image.clone(),
+            Some(_) => {
+                errors.insert("image".to_owned(), "must be a string".to_owned());
+                String::new()
+            }
+        };
+
+        let profile = Profile { name, age, image };
+
+        // report type and range errors together, the type error wins per field
+        validation::validate_with(&profile, errors)?;
+
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile() -> Profile {
+        Profile {
+            name: "Ada".to_string(),
+            age: 36,
+            image: "https://example.com/ada.png".to_string(),
+        }
+    }
+
+    fn patch_errors(patch: Value) -> FieldErrors {
+        match profile().patched(&patch) {
+            Err(ServiceError::ValidationFailed { errors, .. }) => errors,
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_merge_patch_rfc_examples() {
+        let mut target = json!({ "a": "b", "c": { "d": "e", "f": "g" } });
+        merge_patch(&mut target, &json!({ "a": "z", "c": { "f": null } }));
+        assert_eq!(target, json!({ "a": "z", "c": { "d": "e" } }));
+
+        let mut target = json!({ "a": [{ "b": "c" }] });
+        merge_patch(&mut target, &json!({ "a": [1] }));
+        assert_eq!(target, json!({ "a": [1] }));
+
+        let mut target = json!(["a", "b"]);
+        merge_patch(&mut target, &json!({ "a": "b" }));
+        assert_eq!(target, json!({ "a": "b" }));
+    }
+
+    #[test]
+    fn test_patch_changes_only_given_fields() {
+        let patched = profile().patched(&json!({ "age": 37 })).unwrap();
+        assert_eq!(patched, Profile { age: 37, ..profile() });
+
+        let patched = profile().patched(&json!({ "image": null })).unwrap();
+        assert_eq!(patched.image, "");
+    }
+
+    #[test]
+    fn test_patch_validates_every_field() {
+        let errors = patch_errors(json!({ "name": " ", "age": 200, "image": "javascript:alert(1)" }));
+
+        assert_eq!(errors.keys().collect::<Vec<&String>>(), vec!["age", "image", "name"]);
+        assert_eq!(patch_errors(json!({ "name": null }))["name"], "must be a string");
+        assert_eq!(patch_errors(json!({ "age": "old" }))["age"], "must be an integer");
+        assert!(patch_errors(json!({ "email": "a@b.c" })).contains_key("email"));
+        assert!(profile().patched(&json!([])).is_err());
+        assert!(profile().patched(&json!({ "image": "/avatars/1/abc/256.png" })).is_ok());
+    }
+
+    #[test]
+    fn test_if_match() {
+        let now = Utc::now();
+        let current = etag(now);
+
+        assert!(if_match(&current, &current));
+        assert!(if_match(&format!("\"0\", {}", current), &current));
+        assert!(if_match("*", &current));
+        assert!(!if_match(&format!("W/{}", current), &current));
+        assert!(!if_match(&etag(now + Duration::seconds(1)), &current));
+    }
+
+    #[test]
+    fn test_next_updated_at_always_moves_forward() {
+        let previous = Utc::now().duration_trunc(Duration::seconds(1)).unwrap();
+
+        assert_eq!(next_updated_at(previous, previous), previous + Duration::seconds(1));
+        assert_eq!(
+            next_updated_at(previous, previous + Duration::minutes(5)),
+            previous + Duration::minutes(5)
+        );
+    }
+}
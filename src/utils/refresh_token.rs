@@ -0,0 +1,202 @@
+use chrono::{Duration, Utc};
+use derive_more::Display;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, Set, TransactionTrait,
+};
+use uuid::Uuid;
+
+use entities::refresh_token;
+
+use super::constants;
+
+#[derive(Debug, Display)]
+pub enum RefreshError {
+    #[display(fmt = "Invalid refresh token")]
+    Invalid,
+
+    #[display(fmt = "Refresh token expired")]
+    Expired,
+
+    #[display(fmt = "Refresh token reuse detected")]
+    Reused,
+
+    #[display(fmt = "{_0}")]
+    Db(DbErr),
+}
+
+impl From<DbErr> for RefreshError {
+    fn from(err: DbErr) -> Self {
+        RefreshError::Db(err)
+    }
+}
+
+/// Opaque token handed to the client; only its hash is persisted.
+pub fn generate_refresh_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    sha256::digest(token)
+}
+
+/// Stores a fresh refresh token in `family_id` and returns the raw token with its row.
+pub async fn issue_refresh_token<C: ConnectionTrait>(
+    db: &C,
+    user_id: i32,
+    family_id: &str,
+) -> Result<(String, refresh_token::Model), DbErr> {
+    let token = generate_refresh_token();
+    let now = Utc::now().naive_utc();
+
+    let model = refresh_token::ActiveModel {
+        user_id: Set(user_id),
+        family_id: Set(family_id.to_owned()),
+        token_hash: Set(hash_refresh_token(&token)),
+        expires_at: Set(now + Duration::days(*constants::REFRESH_TOKEN_TTL_DAYS)),
+        revoked_at: Set(None),
+        replaced_by_id: Set(None),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok((token, model))
+}
+
+/// Revokes every still-active token of a family.
+pub async fn revoke_family<C: ConnectionTrait>(db: &C, family_id: &str) -> Result<u64, DbErr> {
+    let result = refresh_token::Entity::update_many()
+        .col_expr(
+            refresh_token::Column::RevokedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(refresh_token::Column::FamilyId.eq(family_id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
+
+/// Revokes every still-active token of a user, across all families.
+pub async fn revoke_user_tokens<C: ConnectionTrait>(db: &C, user_id: i32) -> Result<u64, DbErr> {
+    let result = refresh_token::Entity::update_many()
+        .col_expr(
+            refresh_token::Column::RevokedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(refresh_token::Column::UserId.eq(user_id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
+
+/// Exchanges `token` for a new one in the same family.
+///
+/// Presenting a token that was already rotated or revoked is treated as theft:
+/// the whole family is revoked so neither party can keep refreshing.
+pub async fn rotate_refresh_token(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<(String, refresh_token::Model), RefreshError> {
+    let current = refresh_token::Entity::find()
+        .filter(refresh_token::Column::TokenHash.eq(hash_refresh_token(token)))
+        .one(db)
+        .await?
+        .ok_or(RefreshError::Invalid)?;
+
+    if current.revoked_at.is_some() {
+        revoke_family(db, &current.family_id).await?;
+        return Err(RefreshError::Reused);
+    }
+
+    let now = Utc::now().naive_utc();
+    if current.expires_at <= now {
+        return Err(RefreshError::Expired);
+    }
+
+    let txn = db.begin().await?;
+
+    // only one concurrent rotation may claim the token, the loser is a replay
+    let claimed = refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::RevokedAt, Expr::value(now))
+        .filter(refresh_token::Column::Id.eq(current.id))
+        .filter(refresh_token::Column::RevokedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    if claimed.rows_affected == 0 {
+        txn.rollback().await?;
+        revoke_family(db, &current.family_id).await?;
+        return Err(RefreshError::Reused);
+    }
+
+    let (new_token, new_model) = issue_refresh_token(&txn, current.user_id, &current.family_id).await?;
+
+    refresh_token::Entity::update_many()
+        .col_expr(refresh_token::Column::ReplacedById, Expr::value(new_model.id))
+        .filter(refresh_token::Column::Id.eq(current.id))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    Ok((new_token, new_model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_refresh_token_is_unique() {
+        let first = generate_refresh_token();
+        let second = generate_refresh_token();
+
+        assert_eq!(first.len(), 64);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_hash_refresh_token() {
+        let token = generate_refresh_token();
+        let hash = hash_refresh_token(&token);
+
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, token);
+        assert_eq!(hash, hash_refresh_token(&token));
+    }
+
+    #[actix_rt::test]
+    async fn test_rotate_revoked_token_revokes_family() {
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+
+        let now = Utc::now().naive_utc();
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![refresh_token::Model {
+                id: 1,
+                user_id: 42,
+                family_id: "family".to_owned(),
+                token_hash: hash_refresh_token("stolen"),
+                expires_at: now + Duration::days(1),
+                revoked_at: Some(now),
+                replaced_by_id: Some(2),
+                created_at: now,
+            }]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+
+        let result = rotate_refresh_token(&db, "stolen").await;
+
+        assert!(matches!(result, Err(RefreshError::Reused)));
+        assert_eq!(db.into_transaction_log().len(), 2);
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+
+use actix_multipart::Multipart;
+use actix_web::{dev::Payload, http::Method, web, FromRequest, HttpMessage, HttpRequest};
+use futures_util::TryStreamExt;
+use serde_json::{Map, Value};
+use validator::Validate;
+
+use super::validation::{self, FieldErrors};
+use crate::error::{Accepted, ServiceError};
+
+/// Content types a `ValidatedBody` can be sent as.
+pub const ACCEPTED_CONTENT_TYPES: &str =
+    "application/json, application/x-www-form-urlencoded, multipart/form-data";
+
+/// Total size of all text fields of a multipart body.
+const MAX_MULTIPART_TEXT_SIZE: usize = 64 * 1024;
+
+/// Text fields of a form body, or the members of a JSON object. Reading a
+/// field records what is wrong with it instead of failing, so every invalid
+/// field can be reported at once.
+pub struct TextFields {
+    values: HashMap<String, String>,
+    /// JSON members that are not scalars, reported once a model reads them.
+    not_text: HashMap<String, String>,
+    errors: FieldErrors,
+}
+
+impl TextFields {
+    pub fn new(values: HashMap<String, String>) -> Self {
+        TextFields {
+            values,
+            not_text: HashMap::new(),
+            errors: FieldErrors::new(),
+        }
+    }
+
+    /// JSON strings are taken as is and numbers and booleans as their JSON
+    /// text, so `"5"` and `5` both parse as an integer, like a form field would.
+    pub fn from_json(members: Map<String, Value>) -> Self {
+        let mut fields = TextFields::new(HashMap::new());
+
+        for (name, value) in members {
+            match value {
+                Value::Null => {}
+                Value::String(value) => {
+                    fields.values.insert(name, value);
+                }
+                Value::Number(_) | Value::Bool(_) => {
+                    fields.values.insert(name, value.to_string());
+                }
+                Value::Array(_) | Value::Object(_) => {
+                    fields.not_text.insert(name, "must be a string or a number".to_owned());
+                }
+            }
+        }
+
+        fields
+    }
+
+    fn take(&mut self, field: &str) -> Option<String> {
+        let value = self.values.remove(field);
+        if value.is_none() {
+            let message = self.not_text.remove(field).unwrap_or_else(|| "is required".to_owned());
+            self.errors.insert(field.to_owned(), message);
+        }
+
+        value
+    }
+
+    pub fn text(&mut self, field: &str) -> String {
+        self.take(field).unwrap_or_default()
+    }
+
+    pub fn parse<T: FromStr + Default>(&mut self, field: &str) -> T {
+        match self.take(field) {
+            Some(value) => validation::parse_field(&mut self.errors, field, &value),
+            None => T::default(),
+        }
+    }
+
+    pub fn into_errors(self) -> FieldErrors {
+        self.errors
+    }
+}
+
+/// A request model read field by field, whichever way the body was sent.
+pub trait FromTextFields: Sized {
+    fn from_text_fields(fields: &mut TextFields) -> Self;
+}
+
+/// Extracts `T` from a JSON, urlencoded or multipart body, whichever the
+/// `Content-Type` names, and runs its `#[validate]` rules. All three report
+/// invalid fields in the same 422.
+pub struct ValidatedBody<T>(pub T);
+
+impl<T> ValidatedBody<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> FromRequest for ValidatedBody<T>
+where
+    T: Validate + FromTextFields + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let mut payload = payload.take();
+
+        Box::pin(async move {
+            let mime = req.mime_type().map_err(|err| ServiceError::BadRequest {
+                error_message: err.to_string(),
+            })?;
+
+            let fields = match mime.as_ref().map(|mime| mime.essence_str()) {
+                Some("application/json") => {
+                    let members = web::Json::<Map<String, Value>>::from_request(&req, &mut payload).await?;
+                    TextFields::from_json(members.into_inner())
+                }
+                Some("application/x-www-form-urlencoded") => {
+                    let form = web::Form::<HashMap<String, String>>::from_request(&req, &mut payload).await?;
+                    TextFields::new(form.into_inner())
+                }
+                Some("multipart/form-data") => TextFields::new(multipart_text_fields(&req, payload).await?),
+                _ => {
+                    return Err(ServiceError::UnsupportedMediaType {
+                        error_message: format!("Content-Type must be one of {}", ACCEPTED_CONTENT_TYPES),
+                        accepted: (req.method() == Method::POST)
+                            .then(|| Accepted::Post(ACCEPTED_CONTENT_TYPES.to_owned())),
+                    }
+                    .into())
+                }
+            };
+            let (model, errors) = from_text_fields(fields);
+
+            validation::validate_with(&model, errors)?;
+
+            Ok(ValidatedBody(model))
+        })
+    }
+}
+
+fn from_text_fields<T: FromTextFields>(mut fields: TextFields) -> (T, FieldErrors) {
+    let model = T::from_text_fields(&mut fields);
+
+    (model, fields.into_errors())
+}
+
+/// Reads every field of a multipart body as UTF-8 text; files make no sense here.
+async fn multipart_text_fields(
+    req: &HttpRequest,
+    payload: Payload,
+) -> Result<HashMap<String, String>, ServiceError> {
+    let mut multipart = Multipart::new(req.headers(), payload);
+    let mut values = HashMap::new();
+    let mut size = 0;
+
+    while let Some(mut field) = multipart.try_next().await? {
+        let name = field.name().to_owned();
+        let mut value = Vec::new();
+
+        while let Some(chunk) = field.try_next().await? {
+            size += chunk.len();
+            if size > MAX_MULTIPART_TEXT_SIZE {
+                return Err(ServiceError::PayloadTooLarge {
+                    error_message: format!("Form fields may not exceed {} bytes", MAX_MULTIPART_TEXT_SIZE),
+                });
+            }
+            value.extend_from_slice(&chunk);
+        }
+
+        let value = String::from_utf8(value).map_err(|_| ServiceError::BadRequest {
+            error_message: format!("Field {} is not UTF-8 text", name),
+        })?;
+        values.insert(name, value);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header::CONTENT_TYPE, StatusCode};
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    #[derive(Validate, Debug, PartialEq)]
+    struct Transfer {
+        #[validate(custom(function = "validation::chain_address"))]
+        to: String,
+        #[validate(range(min = 1, message = "must be positive"))]
+        amount: i32,
+    }
+
+    impl FromTextFields for Transfer {
+        fn from_text_fields(fields: &mut TextFields) -> Self {
+            Transfer {
+                to: fields.text("to"),
+                amount: fields.parse("amount"),
+            }
+        }
+    }
+
+    const TO: &str = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+
+    async fn extract(req: TestRequest) -> Result<Transfer, actix_web::Error> {
+        let (req, mut payload) = req.to_http_parts();
+
+        ValidatedBody::<Transfer>::from_request(&req, &mut payload)
+            .await
+            .map(ValidatedBody::into_inner)
+    }
+
+    fn field_errors(err: actix_web::Error) -> FieldErrors {
+        match err.as_error::<ServiceError>() {
+            Some(ServiceError::ValidationFailed { errors, .. }) => errors.clone(),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_every_content_type_gives_the_same_model() {
+        let expected = Transfer { to: TO.to_string(), amount: 5 };
+
+        let json = TestRequest::post().set_json(json!({ "to": TO, "amount": 5 }));
+        assert_eq!(extract(json).await.unwrap(), expected);
+
+        let form = TestRequest::post().set_form([("to", TO), ("amount", "5")]);
+        assert_eq!(extract(form).await.unwrap(), expected);
+
+        let boundary = "XyZ";
+        let body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"to\"\r\n\r\n{to}\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"amount\"\r\n\r\n5\r\n--{b}--\r\n",
+            b = boundary,
+            to = TO
+        );
+        let multipart = TestRequest::post()
+            .insert_header((CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary)))
+            .set_payload(body);
+        assert_eq!(extract(multipart).await.unwrap(), expected);
+    }
+
+    #[actix_rt::test]
+    async fn test_form_fields_are_all_checked() {
+        let form = TestRequest::post().set_form([("to", "nowhere"), ("amount", "lots")]);
+        let errors = field_errors(extract(form).await.unwrap_err());
+        assert_eq!(errors["amount"], "must be an integer");
+        assert_eq!(errors["to"], "must be 0x followed by 40 hex digits");
+
+        let form = TestRequest::post().set_form([("amount", "0")]);
+        let errors = field_errors(extract(form).await.unwrap_err());
+        assert_eq!(errors["amount"], "must be positive");
+        assert_eq!(errors["to"], "is required");
+    }
+
+    #[actix_rt::test]
+    async fn test_json_fields_are_checked_like_form_fields() {
+        let json = TestRequest::post().set_json(json!({ "to": "nowhere", "amount": 1.5 }));
+        let errors = field_errors(extract(json).await.unwrap_err());
+        assert_eq!(errors["amount"], "must be an integer");
+        assert_eq!(errors["to"], "must be 0x followed by 40 hex digits");
+
+        let json = TestRequest::post().set_json(json!({ "to": [TO], "amount": 0 }));
+        let errors = field_errors(extract(json).await.unwrap_err());
+        assert_eq!(errors["amount"], "must be positive");
+        assert_eq!(errors["to"], "must be a string or a number");
+
+        let json = TestRequest::post().set_json(json!({ "to": null }));
+        let errors = field_errors(extract(json).await.unwrap_err());
+        assert_eq!(errors["amount"], "is required");
+        assert_eq!(errors["to"], "is required");
+    }
+
+    #[actix_rt::test]
+    async fn test_unknown_content_type_is_rejected() {
+        let text = TestRequest::post()
+            .insert_header((CONTENT_TYPE, "text/plain"))
+            .set_payload("to=me");
+        let res = extract(text).await.unwrap_err().error_response();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(res.headers().get("Accept-Post").unwrap(), ACCEPTED_CONTENT_TYPES);
+
+        let missing = TestRequest::post().set_payload("{}");
+        let err = extract(missing).await.unwrap_err();
+        assert_eq!(err.as_response_error().status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+};
+
+use entities::{oauth_client, revoked_token, user_info, user_session};
+
+use super::constants;
+use super::jwt::{Claims, Principal};
+use super::oauth_client as clients;
+use super::session;
+
+/// Token versions are re-read after this long, so a bump made by another
+/// instance is honoured here within this window.
+const TOKEN_VERSION_CACHE_TTL: StdDuration = StdDuration::from_secs(30);
+
+/// A user's token version as last read from `user_info`.
+#[derive(Clone, Copy, Debug)]
+struct CachedVersion {
+    version: i32,
+    /// Neither disabled nor deleted.
+    active: bool,
+    read_at: Instant,
+}
+
+impl CachedVersion {
+    fn of(user: &user_info::Model) -> Self {
+        CachedVersion {
+            version: user.token_version,
+            active: user.disabled_at.is_none() && user.deleted_at.is_none(),
+            read_at: Instant::now(),
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.read_at.elapsed() < TOKEN_VERSION_CACHE_TTL
+    }
+}
+
+/// Server-side record of revoked access tokens and per-user token versions.
+///
+/// The `revoked_token` table is the source of truth; the maps are a write-through
+/// cache warmed at startup so the auth middleware does not hit the database for
+/// every revocation check.
+#[derive(Default)]
+pub struct RevocationStore {
+    revoked: RwLock<HashMap<String, usize>>,
+    token_versions: RwLock<HashMap<i32, CachedVersion>>,
+    /// Revoked session ids, kept until the last access token of the session expired.
+    revoked_sessions: RwLock<HashMap<i32, usize>>,
+    /// Revoked OAuth2 client ids, kept as long as their tokens could still be alive.
+    revoked_clients: RwLock<HashMap<String, usize>>,
+}
+
+impl RevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops expired rows and loads the remaining revocations into memory.
+    pub async fn load(db: &DatabaseConnection) -> Result<Self, DbErr> {
+        let now = Utc::now().naive_utc();
+
+        revoked_token::Entity::delete_many()
+            .filter(revoked_token::Column::ExpiresAt.lte(now))
+            .exec(db)
+            .await?;
+
+        let revoked = revoked_token::Entity::find()
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|token| (token.jti, token.expires_at.and_utc().timestamp() as usize))
+            .collect();
+
+        let access_ttl = Duration::minutes(*constants::ACCESS_TOKEN_TTL_MINUTES);
+        let revoked_sessions = user_session::Entity::find()
+            .filter(user_session::Column::RevokedAt.gt(now - access_ttl))
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|session| {
+                let revoked_at = session.revoked_at?;
+                Some((session.id, (revoked_at + access_ttl).and_utc().timestamp() as usize))
+            })
+            .collect();
+
+        let revoked_clients = oauth_client::Entity::find()
+            .filter(oauth_client::Column::RevokedAt.gt(now - access_ttl))
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|client| {
+                let revoked_at = client.revoked_at?;
+                Some((client.client_id, (revoked_at + access_ttl).and_utc().timestamp() as usize))
+            })
+            .collect();
+
+        Ok(RevocationStore {
+            revoked: RwLock::new(revoked),
+            token_versions: RwLock::new(HashMap::new()),
+            revoked_sessions: RwLock::new(revoked_sessions),
+            revoked_clients: RwLock::new(revoked_clients),
+        })
+    }
+
+    pub fn is_revoked(&self, jti: &str) -> bool {
+        self.revoked.read().unwrap().contains_key(jti)
+    }
+
+    /// Revokes a single access token until it would have expired anyway.
+    pub async fn revoke(&self, db: &DatabaseConnection, claims: &Claims) -> Result<(), DbErr> {
+        if self.is_revoked(&claims.jti) {
+            return Ok(());
+        }
+
+        let expires_at = DateTime::from_timestamp(claims.exp as i64, 0)
+            .unwrap_or_else(Utc::now)
+            .naive_utc();
+
+        revoked_token::ActiveModel {
+            jti: Set(claims.jti.clone()),
+            user_id: Set(claims.id),
+            expires_at: Set(expires_at),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        let now = Utc::now().timestamp() as usize;
+        let mut revoked = self.revoked.write().unwrap();
+        revoked.retain(|_, exp| *exp > now);
+        revoked.insert(claims.jti.clone(), claims.exp);
+
+        Ok(())
+    }
+
+    /// Current token version of a user, `None` if the user no longer exists
+    /// or may not sign in because the account is disabled or deleted.
+    pub async fn token_version(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<Option<i32>, DbErr> {
+        if let Some(cached) = self.token_versions.read().unwrap().get(&user_id) {
+            if cached.is_fresh() {
+                return Ok(cached.active.then_some(cached.version));
+            }
+        }
+
+        self.reload_token_version(db, user_id).await
+    }
+
+    async fn reload_token_version(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<Option<i32>, DbErr> {
+        let read = user_info::Entity::find_by_id(user_id)
+            .one(db)
+            .await?
+            .map(|user| CachedVersion::of(&user));
+
+        Ok(self.remember_token_version(user_id, read))
+    }
+
+    /// Caches a version read from the database. A read that started before a
+    /// bump finishes after it, so it must not replace the newer version.
+    fn remember_token_version(&self, user_id: i32, read: Option<CachedVersion>) -> Option<i32> {
+        let mut versions = self.token_versions.write().unwrap();
+
+        let Some(read) = read else {
+            versions.remove(&user_id);
+            return None;
+        };
+
+        let cached = versions.entry(user_id).or_insert(read);
+        if read.version >= cached.version || !cached.is_fresh() {
+            *cached = read;
+        }
+
+        cached.active.then_some(cached.version)
+    }
+
+    /// Invalidates every access token issued to the user so far.
+    pub async fn bump_token_version(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> Result<(), DbErr> {
+        user_info::Entity::update_many()
+            .col_expr(
+                user_info::Column::TokenVersion,
+                Expr::col(user_info::Column::TokenVersion).add(1),
+            )
+            .filter(user_info::Column::Id.eq(user_id))
+            .exec(db)
+            .await?;
+
+        // read the new version back rather than dropping the entry, so a
+        // concurrent `token_version` cannot put the old one back
+        self.reload_token_version(db, user_id).await?;
+
+        Ok(())
+    }
+
+    pub fn is_session_revoked(&self, session_id: i32) -> bool {
+        self.revoked_sessions.read().unwrap().contains_key(&session_id)
+    }
+
+    /// Ends sessions: their access tokens stop working here, their refresh
+    /// token families are revoked by the caller.
+    pub async fn revoke_sessions(
+        &self,
+        db: &DatabaseConnection,
+        session_ids: &[i32],
+    ) -> Result<(), DbErr> {
+        session::mark_revoked(db, session_ids).await?;
+
+        let now = Utc::now();
+        let expires = (now + Duration::minutes(*constants::ACCESS_TOKEN_TTL_MINUTES)).timestamp() as usize;
+        let mut revoked_sessions = self.revoked_sessions.write().unwrap();
+        revoked_sessions.retain(|_, exp| *exp > now.timestamp() as usize);
+        for session_id in session_ids {
+            revoked_sessions.insert(*session_id, expires);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_client_revoked(&self, client_id: &str) -> bool {
+        self.revoked_clients.read().unwrap().contains_key(client_id)
+    }
+
+    /// Revokes an OAuth2 client together with the tokens it holds.
+    /// Returns `false` if there was no active client with that id.
+    pub async fn revoke_client(
+        &self,
+        db: &DatabaseConnection,
+        client_id: &str,
+    ) -> Result<bool, DbErr> {
+        if !clients::mark_revoked(db, client_id).await? {
+            return Ok(false);
+        }
+
+        let now = Utc::now();
+        let expires = (now + Duration::minutes(*constants::ACCESS_TOKEN_TTL_MINUTES)).timestamp() as usize;
+        let mut revoked_clients = self.revoked_clients.write().unwrap();
+        revoked_clients.retain(|_, exp| *exp > now.timestamp() as usize);
+        revoked_clients.insert(client_id.to_owned(), expires);
+
+        Ok(true)
+    }
+
+    pub async fn is_token_valid(
+        &self,
+        db: &DatabaseConnection,
+        claims: &Claims,
+    ) -> Result<bool, DbErr> {
+        if self.is_revoked(&claims.jti) || claims.sid.is_some_and(|sid| self.is_session_revoked(sid)) {
+            return Ok(false);
+        }
+
+        match claims.principal() {
+            // clients have no token version, revoking the client is what ends their tokens
+            Principal::Client(client_id) => Ok(!self.is_client_revoked(client_id)),
+            Principal::User(user_id) => {
+                let version = self.token_version(db, user_id).await?;
+
+                Ok(version == Some(claims.ver))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use uuid::Uuid;
+
+    fn test_claims(id: i32, ver: i32) -> Claims {
+        let now = Utc::now();
+        Claims {
+            exp: (now + chrono::Duration::minutes(15)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            email: "test@example.com".to_string(),
+            id,
+            jti: Uuid::new_v4().to_string(),
+            ver,
+            role: Default::default(),
+            scopes: None,
+            typ: Default::default(),
+            sid: None,
+            sub: format!("user:{}", id),
+            aud: None,
+            nbf: None,
+        }
+    }
+
+    fn test_user(id: i32, token_version: i32) -> user_info::Model {
+        user_info::Model {
+            token_version,
+            ..test_support::user(id)
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_revoked_token_is_rejected() {
+        let claims = test_claims(1, 0);
+        let now = Utc::now().naive_utc();
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![revoked_token::Model {
+                id: 1,
+                jti: claims.jti.clone(),
+                user_id: 1,
+                expires_at: now,
+                created_at: now,
+            }]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 1,
+                rows_affected: 1,
+            }])
+            .into_connection();
+        let store = RevocationStore::new();
+
+        store.revoke(&db, &claims).await.unwrap();
+
+        assert!(store.is_revoked(&claims.jti));
+        assert!(!store.is_token_valid(&db, &claims).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_token_version_is_checked_and_cached() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![test_user(1, 2)]])
+            .into_connection();
+        let store = RevocationStore::new();
+
+        assert!(!store.is_token_valid(&db, &test_claims(1, 1)).await.unwrap());
+        // served from the cache, the mock has no second result
+        assert!(store.is_token_valid(&db, &test_claims(1, 2)).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_disabled_or_deleted_user_has_no_token_version() {
+        let mut disabled = test_user(1, 0);
+        disabled.disabled_at = Some(Utc::now());
+        let mut deleted = test_user(2, 0);
+        deleted.deleted_at = Some(Utc::now());
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![disabled], vec![deleted]])
+            .into_connection();
+        let store = RevocationStore::new();
+
+        assert!(!store.is_token_valid(&db, &test_claims(1, 0)).await.unwrap());
+        assert!(!store.is_token_valid(&db, &test_claims(2, 0)).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_bump_token_version_updates_cache() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![test_user(1, 0)], vec![test_user(1, 1)]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+        let store = RevocationStore::new();
+
+        assert_eq!(store.token_version(&db, 1).await.unwrap(), Some(0));
+        store.bump_token_version(&db, 1).await.unwrap();
+        assert_eq!(store.token_version(&db, 1).await.unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_stale_read_does_not_undo_a_bump() {
+        let store = RevocationStore::new();
+        let before_bump = CachedVersion::of(&test_user(1, 0));
+
+        assert_eq!(store.remember_token_version(1, Some(CachedVersion::of(&test_user(1, 1)))), Some(1));
+        assert_eq!(store.remember_token_version(1, Some(before_bump)), Some(1));
+
+        // once the entry is stale, whatever the database says wins again
+        store.token_versions.write().unwrap().get_mut(&1).unwrap().read_at -= TOKEN_VERSION_CACHE_TTL;
+        assert_eq!(store.remember_token_version(1, Some(before_bump)), Some(0));
+    }
+
+    #[actix_rt::test]
+    async fn test_token_version_is_reread_after_the_ttl() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![test_user(1, 0)], vec![test_user(1, 3)]])
+            .into_connection();
+        let store = RevocationStore::new();
+
+        assert_eq!(store.token_version(&db, 1).await.unwrap(), Some(0));
+        store.token_versions.write().unwrap().get_mut(&1).unwrap().read_at -= TOKEN_VERSION_CACHE_TTL;
+        assert_eq!(store.token_version(&db, 1).await.unwrap(), Some(3));
+    }
+
+    #[actix_rt::test]
+    async fn test_revoked_session_is_rejected() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![test_user(1, 0)]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+        let store = RevocationStore::new();
+
+        let mut current = test_claims(1, 0);
+        current.sid = Some(10);
+        let mut other = test_claims(1, 0);
+        other.sid = Some(11);
+
+        store.revoke_sessions(&db, &[11]).await.unwrap();
+
+        assert!(store.is_session_revoked(11));
+        assert!(!store.is_token_valid(&db, &other).await.unwrap());
+        assert!(store.is_token_valid(&db, &current).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn test_revoked_client_is_rejected() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+        let store = RevocationStore::new();
+
+        let mut claims = test_claims(0, 0);
+        claims.sub = "client:rsc_ingest".to_string();
+
+        // no user lookup happens for clients, the mock has no query results
+        assert!(store.is_token_valid(&db, &claims).await.unwrap());
+
+        assert!(store.revoke_client(&db, "rsc_ingest").await.unwrap());
+        assert!(!store.is_token_valid(&db, &claims).await.unwrap());
+    }
+}
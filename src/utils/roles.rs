@@ -0,0 +1,152 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Reader,
+    Ingestor,
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    #[serde(rename = "block:read")]
+    BlockRead,
+    #[serde(rename = "block:write")]
+    BlockWrite,
+    #[serde(rename = "tx:read")]
+    TxRead,
+    #[serde(rename = "tx:write")]
+    TxWrite,
+    #[serde(rename = "user:admin")]
+    UserAdmin,
+}
+
+const READER_PERMISSIONS: &[Permission] = &[Permission::BlockRead, Permission::TxRead];
+
+const INGESTOR_PERMISSIONS: &[Permission] = &[
+    Permission::BlockRead,
+    Permission::BlockWrite,
+    Permission::TxRead,
+    Permission::TxWrite,
+];
+
+const ADMIN_PERMISSIONS: &[Permission] = &[
+    Permission::BlockRead,
+    Permission::BlockWrite,
+    Permission::TxRead,
+    Permission::TxWrite,
+    Permission::UserAdmin,
+];
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Ingestor => "ingestor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Reader => READER_PERMISSIONS,
+            Role::Ingestor => INGESTOR_PERMISSIONS,
+            Role::Admin => ADMIN_PERMISSIONS,
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+
+    /// Roles are ordered, so a higher role satisfies any lower requirement.
+    pub fn includes(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reader" => Ok(Role::Reader),
+            "ingestor" => Ok(Role::Ingestor),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unknown role: {}", s)),
+        }
+    }
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::BlockRead => "block:read",
+            Permission::BlockWrite => "block:write",
+            Permission::TxRead => "tx:read",
+            Permission::TxWrite => "tx:write",
+            Permission::UserAdmin => "user:admin",
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block:read" => Ok(Permission::BlockRead),
+            "block:write" => Ok(Permission::BlockWrite),
+            "tx:read" => Ok(Permission::TxRead),
+            "tx:write" => Ok(Permission::TxWrite),
+            "user:admin" => Ok(Permission::UserAdmin),
+            _ => Err(format!("Unknown permission: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(!Role::Reader.has_permission(Permission::BlockWrite));
+        assert!(Role::Ingestor.has_permission(Permission::BlockWrite));
+        assert!(Role::Ingestor.has_permission(Permission::TxWrite));
+        assert!(!Role::Ingestor.has_permission(Permission::UserAdmin));
+        assert!(Role::Admin.has_permission(Permission::UserAdmin));
+    }
+
+    #[test]
+    fn test_role_includes_lower_roles() {
+        assert!(Role::Admin.includes(Role::Ingestor));
+        assert!(Role::Ingestor.includes(Role::Reader));
+        assert!(!Role::Reader.includes(Role::Ingestor));
+    }
+
+    #[test]
+    fn test_role_round_trip() {
+        for role in [Role::Reader, Role::Ingestor, Role::Admin] {
+            assert_eq!(role.as_str().parse::<Role>().unwrap(), role);
+            assert_eq!(
+                serde_json::to_string(&role).unwrap(),
+                format!("\"{}\"", role.as_str())
+            );
+        }
+        assert!("root".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn test_permission_round_trip() {
+        let permission: Permission = serde_json::from_str("\"block:write\"").unwrap();
+
+        assert_eq!(permission, Permission::BlockWrite);
+        assert_eq!("tx:read".parse::<Permission>().unwrap(), Permission::TxRead);
+        assert!("block:delete".parse::<Permission>().is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, Set,
+};
+
+use entities::user_session;
+
+use super::constants;
+use super::refresh_token;
+use super::revocation::RevocationStore;
+
+const MAX_USER_AGENT_LEN: usize = 512;
+/// How stale `last_seen_at` may get while a session only uses its access token.
+const ACTIVITY_WRITE_INTERVAL_SECONDS: i64 = 60;
+
+/// Where a login came from, as shown in the session list.
+pub struct ClientInfo {
+    pub user_agent: String,
+    pub ip_address: String,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .chars()
+            .take(MAX_USER_AGENT_LEN)
+            .collect();
+
+        ClientInfo {
+            user_agent,
+            ip_address: req
+                .peer_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Records a login; the session lives as long as its refresh token family.
+pub async fn create_session(
+    db: &DatabaseConnection,
+    user_id: i32,
+    family_id: &str,
+    client: &ClientInfo,
+) -> Result<user_session::Model, DbErr> {
+    let now = Utc::now().naive_utc();
+
+    user_session::ActiveModel {
+        user_id: Set(user_id),
+        family_id: Set(family_id.to_owned()),
+        user_agent: Set(client.user_agent.clone()),
+        ip_address: Set(client.ip_address.clone()),
+        created_at: Set(now),
+        last_seen_at: Set(now),
+        revoked_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// Bumps `last_seen_at` of the session behind a refresh token family.
+pub async fn touch_session(
+    db: &DatabaseConnection,
+    family_id: &str,
+    client: &ClientInfo,
+) -> Result<Option<user_session::Model>, DbErr> {
+    let session = match user_session::Entity::find()
+        .filter(user_session::Column::FamilyId.eq(family_id))
+        .one(db)
+        .await?
+    {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+
+    let mut session_model: user_session::ActiveModel = session.into();
+    session_model.last_seen_at = Set(Utc::now().naive_utc());
+    session_model.ip_address = Set(client.ip_address.clone());
+
+    session_model.update(db).await.map(Some)
+}
+
+/// Bumps `last_seen_at` when an access token of the session is used. The
+/// condition keeps it to one write per `ACTIVITY_WRITE_INTERVAL_SECONDS`.
+pub async fn record_activity(
+    db: &DatabaseConnection,
+    user_id: i32,
+    session_id: i32,
+) -> Result<(), DbErr> {
+    let now = Utc::now().naive_utc();
+
+    user_session::Entity::update_many()
+        .col_expr(user_session::Column::LastSeenAt, Expr::value(now))
+        .filter(user_session::Column::Id.eq(session_id))
+        .filter(user_session::Column::UserId.eq(user_id))
+        .filter(user_session::Column::RevokedAt.is_null())
+        .filter(
+            user_session::Column::LastSeenAt
+                .lt(now - Duration::seconds(ACTIVITY_WRITE_INTERVAL_SECONDS)),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Sessions that are neither revoked nor past their refresh token lifetime.
+pub async fn active_sessions(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<user_session::Model>, DbErr> {
+    let cutoff = Utc::now().naive_utc() - Duration::days(*constants::REFRESH_TOKEN_TTL_DAYS);
+
+    user_session::Entity::find()
+        .filter(user_session::Column::UserId.eq(user_id))
+        .filter(user_session::Column::RevokedAt.is_null())
+        .filter(user_session::Column::LastSeenAt.gt(cutoff))
+        .order_by_desc(user_session::Column::LastSeenAt)
+        .all(db)
+        .await
+}
+
+pub async fn find_active_session(
+    db: &DatabaseConnection,
+    user_id: i32,
+    session_id: i32,
+) -> Result<Option<user_session::Model>, DbErr> {
+    user_session::Entity::find_by_id(session_id)
+        .filter(user_session::Column::UserId.eq(user_id))
+        .filter(user_session::Column::RevokedAt.is_null())
+        .one(db)
+        .await
+}
+
+/// Signs the sessions out: access tokens via the revocation store, refresh tokens by family.
+pub async fn end_sessions(
+    db: &DatabaseConnection,
+    revocations: &RevocationStore,
+    sessions: &[user_session::Model],
+) -> Result<(), DbErr> {
+    for session in sessions {
+        refresh_token::revoke_family(db, &session.family_id).await?;
+    }
+
+    let session_ids = sessions.iter().map(|session| session.id).collect::<Vec<i32>>();
+    revocations.revoke_sessions(db, &session_ids).await
+}
+
+/// Ends every login of the user: access tokens by version, refresh tokens and sessions.
+pub async fn end_all_logins(
+    db: &DatabaseConnection,
+    revocations: &RevocationStore,
+    user_id: i32,
+) -> Result<(), DbErr> {
+    revocations.bump_token_version(db, user_id).await?;
+
+    let user_sessions = active_sessions(db, user_id).await?;
+    end_sessions(db, revocations, &user_sessions).await?;
+
+    refresh_token::revoke_user_tokens(db, user_id).await?;
+
+    Ok(())
+}
+
+pub async fn mark_revoked(db: &DatabaseConnection, session_ids: &[i32]) -> Result<(), DbErr> {
+    if session_ids.is_empty() {
+        return Ok(());
+    }
+
+    user_session::Entity::update_many()
+        .col_expr(user_session::Column::RevokedAt, Expr::value(Utc::now().naive_utc()))
+        .filter(user_session::Column::Id.is_in(session_ids.to_vec()))
+        .filter(user_session::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+
+    #[test]
+    fn test_client_info_from_request() {
+        let req = TestRequest::default()
+            .insert_header((USER_AGENT, "curl/8.0"))
+            .peer_addr("192.168.1.20:5000".parse().unwrap())
+            .to_http_request();
+
+        let client = ClientInfo::from_request(&req);
+
+        assert_eq!(client.user_agent, "curl/8.0");
+        assert_eq!(client.ip_address, "192.168.1.20");
+    }
+
+    #[test]
+    fn test_client_info_truncates_user_agent() {
+        let req = TestRequest::default()
+            .insert_header((USER_AGENT, "a".repeat(2000)))
+            .to_http_request();
+
+        let client = ClientInfo::from_request(&req);
+
+        assert_eq!(client.user_agent.len(), MAX_USER_AGENT_LEN);
+        assert_eq!(client.ip_address, "");
+    }
+
+    #[actix_rt::test]
+    async fn test_record_activity_only_touches_stale_live_sessions() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 0,
+            }])
+            .into_connection();
+
+        record_activity(&db, 7, 3).await.unwrap();
+
+        let update = format!("{:?}", db.into_transaction_log()[0]);
+        assert!(update.contains("UPDATE `user_session` SET `last_seen_at`"));
+        assert!(update.contains("`revoked_at` IS NULL"));
+        assert!(update.contains("`last_seen_at` <"));
+    }
+}
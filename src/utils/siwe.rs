@@ -0,0 +1,281 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use derive_more::Display;
+
+use super::eth;
+
+const PREAMBLE_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+
+#[derive(Debug, Display, PartialEq)]
+pub enum SiweError {
+    #[display(fmt = "Malformed SIWE message: {_0}")]
+    Malformed(String),
+
+    #[display(fmt = "SIWE message is expired or not yet valid")]
+    OutsideValidity,
+}
+
+/// An EIP-4361 "Sign-In with Ethereum" message.
+#[derive(Debug, PartialEq)]
+pub struct SiweMessage {
+    pub domain: String,
+    /// Lowercased `0x` address.
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+fn malformed(reason: &str) -> SiweError {
+    SiweError::Malformed(reason.to_owned())
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, SiweError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| malformed("invalid timestamp"))
+}
+
+impl SiweMessage {
+    pub fn is_valid_at(&self, now: DateTime<Utc>) -> bool {
+        self.expiration_time.is_none_or(|expiration| now < expiration)
+            && self.not_before.is_none_or(|not_before| now >= not_before)
+    }
+
+    /// Checks validity window and that `signature` was made by `self.address`.
+    pub fn verify(&self, message: &str, signature: &str, now: DateTime<Utc>) -> Result<bool, SiweError> {
+        if !self.is_valid_at(now) {
+            return Err(SiweError::OutsideValidity);
+        }
+
+        Ok(eth::recover_address(message, signature).is_ok_and(|address| address == self.address))
+    }
+}
+
+/// Renders the message in the EIP-4361 text form the wallet signs.
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |time: &DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        writeln!(f, "{}{}", self.domain, PREAMBLE_SUFFIX)?;
+        writeln!(
+            f,
+            "{}",
+            eth::to_checksum_address(&self.address).unwrap_or_else(|| self.address.clone())
+        )?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{}", statement)?;
+            writeln!(f)?;
+        }
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", time(&self.issued_at))?;
+        if let Some(expiration_time) = &self.expiration_time {
+            write!(f, "\nExpiration Time: {}", time(expiration_time))?;
+        }
+        if let Some(not_before) = &self.not_before {
+            write!(f, "\nNot Before: {}", time(not_before))?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\nRequest ID: {}", request_id)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {}", resource)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for SiweMessage {
+    type Err = SiweError;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        let mut lines = message.split('\n').peekable();
+
+        let domain = lines
+            .next()
+            .and_then(|line| line.strip_suffix(PREAMBLE_SUFFIX))
+            .filter(|domain| !domain.is_empty())
+            .ok_or(malformed("missing preamble"))?
+            .to_owned();
+
+        let address = lines
+            .next()
+            .and_then(eth::normalize_address)
+            .ok_or(malformed("invalid address"))?;
+
+        if lines.next() != Some("") {
+            return Err(malformed("expected blank line after address"));
+        }
+
+        let statement = match lines.peek() {
+            Some(line) if !line.starts_with("URI: ") => {
+                let statement = (*line).to_owned();
+                lines.next();
+                if lines.next() != Some("") {
+                    return Err(malformed("expected blank line after statement"));
+                }
+                Some(statement)
+            }
+            _ => None,
+        };
+
+        let mut field = |tag: &str, required: bool| -> Result<Option<String>, SiweError> {
+            match lines.peek().and_then(|line| line.strip_prefix(tag)) {
+                Some(value) => {
+                    let value = value.to_owned();
+                    lines.next();
+                    Ok(Some(value))
+                }
+                None if required => Err(SiweError::Malformed(format!("missing {}", tag.trim_end_matches(": ")))),
+                None => Ok(None),
+            }
+        };
+
+        let uri = field("URI: ", true)?.unwrap_or_default();
+        let version = field("Version: ", true)?.unwrap_or_default();
+        let chain_id = field("Chain ID: ", true)?
+            .unwrap_or_default()
+            .parse::<u64>()
+            .map_err(|_| malformed("invalid chain id"))?;
+        let nonce = field("Nonce: ", true)?.unwrap_or_default();
+        let issued_at = parse_time(&field("Issued At: ", true)?.unwrap_or_default())?;
+        let expiration_time = field("Expiration Time: ", false)?
+            .map(|value| parse_time(&value))
+            .transpose()?;
+        let not_before = field("Not Before: ", false)?
+            .map(|value| parse_time(&value))
+            .transpose()?;
+        let request_id = field("Request ID: ", false)?;
+
+        let mut resources = Vec::new();
+        if lines.peek() == Some(&"Resources:") {
+            lines.next();
+            while let Some(resource) = lines.peek().and_then(|line| line.strip_prefix("- ")) {
+                resources.push(resource.to_owned());
+                lines.next();
+            }
+        }
+
+        if lines.any(|line| !line.is_empty()) {
+            return Err(malformed("unexpected trailing content"));
+        }
+
+        if version != "1" {
+            return Err(malformed("unsupported version"));
+        }
+
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(malformed("invalid nonce"));
+        }
+
+        Ok(SiweMessage {
+            domain,
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::eth::tests::{sign_message, test_key};
+
+    const MESSAGE: &str = "example.com wants you to sign in with your Ethereum account:
+0x2c7536E3605D9C16a7a3D7b1898e529396a65c23
+
+Sign in to rust_server
+
+URI: https://example.com/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756abcdef01
+Issued At: 2024-01-01T00:00:00Z
+Expiration Time: 2024-01-01T00:10:00Z
+Resources:
+- https://example.com/terms";
+
+    fn at(time: &str) -> DateTime<Utc> {
+        parse_time(time).unwrap()
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let message = MESSAGE.parse::<SiweMessage>().unwrap();
+
+        assert_eq!(message.domain, "example.com");
+        assert_eq!(message.address, "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23");
+        assert_eq!(message.statement.as_deref(), Some("Sign in to rust_server"));
+        assert_eq!(message.chain_id, 1);
+        assert_eq!(message.nonce, "32891756abcdef01");
+        assert_eq!(message.expiration_time, Some(at("2024-01-01T00:10:00Z")));
+        assert_eq!(message.resources, vec!["https://example.com/terms"]);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let message = MESSAGE.parse::<SiweMessage>().unwrap();
+
+        assert_eq!(message.to_string(), MESSAGE);
+
+        let without_statement = MESSAGE.replace("Sign in to rust_server\n\n", "");
+        assert_eq!(without_statement.parse::<SiweMessage>().unwrap().to_string(), without_statement);
+    }
+
+    #[test]
+    fn test_parse_message_without_statement() {
+        let message = MESSAGE.replace("Sign in to rust_server\n\n", "");
+
+        assert_eq!(message.parse::<SiweMessage>().unwrap().statement, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_message() {
+        assert!("hello".parse::<SiweMessage>().is_err());
+        assert!(MESSAGE.replace("Version: 1", "Version: 2").parse::<SiweMessage>().is_err());
+        assert!(MESSAGE.replace("Nonce: 32891756abcdef01", "Nonce: abc").parse::<SiweMessage>().is_err());
+        assert!(MESSAGE.replace("Chain ID: 1\n", "").parse::<SiweMessage>().is_err());
+    }
+
+    #[test]
+    fn test_verify_signature_and_validity() {
+        let message = MESSAGE.parse::<SiweMessage>().unwrap();
+        let signature = sign_message(&test_key(), MESSAGE);
+
+        assert_eq!(message.verify(MESSAGE, &signature, at("2024-01-01T00:05:00Z")), Ok(true));
+        assert_eq!(
+            message.verify(&MESSAGE.replace("Chain ID: 1", "Chain ID: 5"), &signature, at("2024-01-01T00:05:00Z")),
+            Ok(false)
+        );
+        assert_eq!(
+            message.verify(MESSAGE, &signature, at("2024-01-01T00:11:00Z")),
+            Err(SiweError::OutsideValidity)
+        );
+    }
+}
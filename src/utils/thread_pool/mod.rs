@@ -1 +1,2 @@
-pub mod thread_pool; 
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod thread_pool;
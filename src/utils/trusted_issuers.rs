@@ -0,0 +1,556 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use derive_more::Display;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use entities::{user_identity, user_info};
+
+use super::constants;
+use super::jwt::{Claims, TokenType};
+use super::password;
+
+/// A JWKS is refetched for an unknown `kid` at most this often, so garbage
+/// tokens cannot turn the server into a proxy hammering the identity provider.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Display)]
+pub enum TrustedIssuerError {
+    #[display(fmt = "Trusted issuer configuration error: {_0}")]
+    Config(String),
+
+    #[display(fmt = "Could not load JWKS: {_0}")]
+    Jwks(String),
+
+    #[display(fmt = "Invalid token: {_0}")]
+    Invalid(String),
+
+    /// Failed decoding or validation, kept as is so it maps to the same
+    /// problem codes as a local token would.
+    #[display(fmt = "Invalid token: {_0}")]
+    Token(jsonwebtoken::errors::Error),
+
+    #[display(fmt = "No account for this identity")]
+    UnknownUser,
+
+    #[display(fmt = "{_0}")]
+    Db(DbErr),
+}
+
+/// One entry of the `TRUSTED_ISSUERS_PATH` file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct IssuerConfig {
+    /// Must equal the `iss` claim.
+    pub issuer: String,
+    /// Must be contained in the `aud` claim.
+    pub audience: String,
+    pub jwks_url: Option<String>,
+    pub jwks_path: Option<PathBuf>,
+    /// Create a local user on the first request of an unknown identity.
+    #[serde(default)]
+    pub create_users: bool,
+}
+
+/// Claims of a verified external token that matter for mapping it to a user.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ExternalClaims {
+    pub iss: String,
+    pub sub: String,
+    pub exp: usize,
+    pub iat: Option<usize>,
+    pub jti: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub name: Option<String>,
+}
+
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+pub struct TrustedIssuer {
+    config: IssuerConfig,
+    jwks: RwLock<Option<CachedJwks>>,
+}
+
+impl TrustedIssuer {
+    pub fn new(config: IssuerConfig) -> Result<Self, TrustedIssuerError> {
+        if config.jwks_url.is_some() == config.jwks_path.is_some() {
+            return Err(TrustedIssuerError::Config(format!(
+                "{} needs exactly one of jwks_url and jwks_path",
+                config.issuer
+            )));
+        }
+
+        Ok(TrustedIssuer {
+            config,
+            jwks: RwLock::new(None),
+        })
+    }
+
+    /// Key set from a file never changes at runtime, one from a URL is cached for `JWKS_CACHE_SECONDS`.
+    fn cached_jwks(&self, kid: &str) -> Option<JwkSet> {
+        let cache = self.jwks.read().unwrap();
+        let cached = cache.as_ref()?;
+
+        let fresh = self.config.jwks_path.is_some()
+            || cached.fetched_at.elapsed() < Duration::from_secs(*constants::JWKS_CACHE_SECONDS);
+        // an unknown kid usually means the provider rotated its keys
+        let throttled = cached.fetched_at.elapsed() < MIN_REFRESH_INTERVAL;
+
+        if fresh && (cached.keys.find(kid).is_some() || throttled) {
+            return Some(cached.keys.clone());
+        }
+
+        None
+    }
+
+    async fn load_jwks(&self, http: &reqwest::Client) -> Result<JwkSet, TrustedIssuerError> {
+        let body = match (&self.config.jwks_path, &self.config.jwks_url) {
+            (Some(path), _) => {
+                fs::read_to_string(path).map_err(|err| TrustedIssuerError::Jwks(err.to_string()))?
+            }
+            (None, Some(url)) => http
+                .get(url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|err| TrustedIssuerError::Jwks(err.to_string()))?
+                .text()
+                .await
+                .map_err(|err| TrustedIssuerError::Jwks(err.to_string()))?,
+            (None, None) => return Err(TrustedIssuerError::Config(self.config.issuer.clone())),
+        };
+
+        let keys = serde_json::from_str::<JwkSet>(&body)
+            .map_err(|err| TrustedIssuerError::Jwks(err.to_string()))?;
+
+        *self.jwks.write().unwrap() = Some(CachedJwks {
+            keys: keys.clone(),
+            fetched_at: Instant::now(),
+        });
+
+        Ok(keys)
+    }
+
+    async fn verify(
+        &self,
+        http: &reqwest::Client,
+        token: &str,
+    ) -> Result<ExternalClaims, TrustedIssuerError> {
+        let header = decode_header(token).map_err(TrustedIssuerError::Token)?;
+
+        // external tokens are verified with public keys only, never a shared secret
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(TrustedIssuerError::Invalid("Symmetric algorithms are not accepted".to_owned()));
+        }
+
+        let kid = header
+            .kid
+            .ok_or(TrustedIssuerError::Invalid("Missing kid".to_owned()))?;
+
+        let keys = match self.cached_jwks(&kid) {
+            Some(keys) => keys,
+            None => self.load_jwks(http).await?,
+        };
+
+        let jwk = keys
+            .find(&kid)
+            .ok_or(TrustedIssuerError::Invalid(format!("Unknown kid {}", kid)))?;
+
+        if jwk
+            .common
+            .key_algorithm
+            .is_some_and(|key_algorithm| key_algorithm.to_string() != format!("{:?}", header.alg))
+        {
+            return Err(TrustedIssuerError::Invalid("Algorithm does not match the key".to_owned()));
+        }
+
+        let key = DecodingKey::from_jwk(jwk).map_err(TrustedIssuerError::Token)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        decode::<ExternalClaims>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(TrustedIssuerError::Token)
+    }
+}
+
+#[derive(Deserialize)]
+struct UnverifiedIssuer {
+    iss: Option<String>,
+}
+
+/// Issuer of a token, read *without* verifying it. Only used to pick the issuer
+/// whose keys must then verify the token.
+pub fn unverified_issuer(token: &str) -> Option<String> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload).ok()?;
+
+    serde_json::from_slice::<UnverifiedIssuer>(&bytes).ok()?.iss
+}
+
+/// External identity providers whose tokens `check_auth_middleware` accepts
+/// next to the ones this server mints itself.
+#[derive(Default)]
+pub struct TrustedIssuers {
+    issuers: Vec<TrustedIssuer>,
+    http: reqwest::Client,
+}
+
+impl TrustedIssuers {
+    pub fn new(configs: Vec<IssuerConfig>) -> Result<Self, TrustedIssuerError> {
+        let issuers = configs
+            .into_iter()
+            .map(TrustedIssuer::new)
+            .collect::<Result<Vec<TrustedIssuer>, TrustedIssuerError>>()?;
+
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|err| TrustedIssuerError::Config(err.to_string()))?;
+
+        Ok(TrustedIssuers { issuers, http })
+    }
+
+    /// Reads `TRUSTED_ISSUERS_PATH`; without it only local tokens are accepted.
+    pub fn from_config() -> Result<Self, TrustedIssuerError> {
+        let configs = match constants::TRUSTED_ISSUERS_PATH.as_ref() {
+            Some(path) => {
+                let content =
+                    fs::read_to_string(path).map_err(|err| TrustedIssuerError::Config(err.to_string()))?;
+                serde_json::from_str::<Vec<IssuerConfig>>(&content)
+                    .map_err(|err| TrustedIssuerError::Config(err.to_string()))?
+            }
+            None => Vec::new(),
+        };
+
+        Self::new(configs)
+    }
+
+    /// The trusted issuer a token claims to come from, if any.
+    pub fn issuer_for(&self, token: &str) -> Option<&TrustedIssuer> {
+        if self.issuers.is_empty() {
+            return None;
+        }
+
+        let iss = unverified_issuer(token)?;
+        self.issuers.iter().find(|issuer| issuer.config.issuer == iss)
+    }
+
+    /// Verifies an external token and maps it to local claims.
+    pub async fn authenticate(
+        &self,
+        db: &DatabaseConnection,
+        issuer: &TrustedIssuer,
+        token: &str,
+    ) -> Result<Claims, TrustedIssuerError> {
+        let external = issuer.verify(&self.http, token).await?;
+        let user = resolve_user(db, &issuer.config, &external)
+            .await
+            .map_err(TrustedIssuerError::Db)?
+            .ok_or(TrustedIssuerError::UnknownUser)?;
+
+        Ok(local_claims(&user, &external))
+    }
+}
+
+/// Whether the account signs in only through SSO: it never had a password and
+/// has a linked identity. A forced reset does not count, see `password::RESET_REQUIRED`.
+pub async fn is_sso_only(db: &DatabaseConnection, user: &user_info::Model) -> Result<bool, DbErr> {
+    if !password::never_set(&user.password) {
+        return Ok(false);
+    }
+
+    let identity = user_identity::Entity::find()
+        .filter(user_identity::Column::UserId.eq(user.id))
+        .one(db)
+        .await?;
+
+    Ok(identity.is_some())
+}
+
+/// Finds the local user of an external identity: by a previously linked
+/// `(iss, sub)`, then by email, then by creating one if the issuer allows it.
+pub async fn resolve_user(
+    db: &DatabaseConnection,
+    config: &IssuerConfig,
+    external: &ExternalClaims,
+) -> Result<Option<user_info::Model>, DbErr> {
+    let identity = user_identity::Entity::find()
+        .filter(user_identity::Column::Issuer.eq(&external.iss))
+        .filter(user_identity::Column::Subject.eq(&external.sub))
+        .one(db)
+        .await?;
+
+    if let Some(identity) = identity {
+        return user_info::Entity::find_by_id(identity.user_id)
+            .filter(user_info::Column::DeletedAt.is_null())
+            .one(db)
+            .await;
+    }
+
+    // only an address the provider vouches for may be matched against local accounts
+    let email = match &external.email {
+        Some(email) if external.email_verified == Some(true) => email,
+        _ => return Ok(None),
+    };
+
+    let existing = user_info::Entity::find()
+        .filter(user_info::Column::Email.eq(email))
+        .one(db)
+        .await?;
+
+    let user = match existing {
+        // a deleted account is not revived, nor replaced while its email is taken
+        Some(user) if user.deleted_at.is_some() => return Ok(None),
+        // whoever registered an unverified account may not own the address
+        Some(user) if !user.email_verified => return Ok(None),
+        Some(user) => user,
+        None if config.create_users => {
+            user_info::ActiveModel {
+                name: Set(external.name.clone().unwrap_or_else(|| email.clone())),
+                age: Set(0),
+                image: Set(String::new()),
+                email: Set(email.clone()),
+                // no password, the account signs in through its identity provider
+                password: Set(String::new()),
+                wallet_address: Set(String::new()),
+                email_verified: Set(true),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?
+        }
+        None => return Ok(None),
+    };
+
+    user_identity::ActiveModel {
+        user_id: Set(user.id),
+        issuer: Set(external.iss.clone()),
+        subject: Set(external.sub.clone()),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    Ok(Some(user))
+}
+
+/// Local view of an external token: the mapped user, with the external lifetime.
+pub fn local_claims(user: &user_info::Model, external: &ExternalClaims) -> Claims {
+    Claims {
+        exp: external.exp,
+        iat: external.iat.unwrap_or_else(|| Utc::now().timestamp() as usize),
+        email: user.email.clone(),
+        id: user.id,
+        // stable per external token so logout can revoke it, and short enough for `revoked_token.jti`
+        jti: external
+            .jti
+            .as_ref()
+            .map(|jti| format!("sso-{}", &sha256::digest(format!("{}#{}", external.iss, jti))[..32]))
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        ver: user.token_version,
+        role: user.role.parse().unwrap_or_default(),
+        scopes: None,
+        typ: TokenType::Access,
+        sid: None,
+        sub: format!("user:{}", user.id),
+        aud: None,
+        nbf: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support;
+    use crate::utils::test_support::{jwks_file, sso_issuer_config as test_config};
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    use serde_json::json;
+
+    fn sso_token(kid: &str, aud: &str) -> String {
+        test_support::sso_token(kid, json!({ "aud": aud }))
+    }
+
+    fn test_user(id: i32) -> user_info::Model {
+        user_info::Model {
+            name: "Jane".to_string(),
+            age: 0,
+            email: "jane@example.com".to_string(),
+            token_version: 2,
+            role: "ingestor".to_string(),
+            email_verified: true,
+            ..test_support::user(id)
+        }
+    }
+
+    #[test]
+    fn test_config_needs_one_jwks_source() {
+        let mut config = test_config(PathBuf::from("jwks.json"));
+        config.jwks_url = Some("https://sso.example.com/jwks".to_string());
+
+        assert!(TrustedIssuer::new(config).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_verifies_token_from_trusted_issuer() {
+        let issuers = TrustedIssuers::new(vec![test_config(jwks_file())]).unwrap();
+        let token = sso_token("sso-1", "rust_server");
+
+        let issuer = issuers.issuer_for(&token).unwrap();
+        let external = issuer.verify(&issuers.http, &token).await.unwrap();
+
+        assert_eq!(external.sub, "00u1abc");
+        assert_eq!(external.email.as_deref(), Some("jane@example.com"));
+    }
+
+    #[actix_rt::test]
+    async fn test_rejects_wrong_audience_and_unknown_key() {
+        let issuers = TrustedIssuers::new(vec![test_config(jwks_file())]).unwrap();
+        let issuer = &issuers.issuers[0];
+
+        assert!(issuer.verify(&issuers.http, &sso_token("sso-1", "other")).await.is_err());
+        assert!(issuer.verify(&issuers.http, &sso_token("sso-2", "rust_server")).await.is_err());
+    }
+
+    #[test]
+    fn test_local_tokens_have_no_trusted_issuer() {
+        let issuers = TrustedIssuers::new(vec![test_config(jwks_file())]).unwrap();
+        let local = super::super::jwt_keys::KEYS
+            .encode(&json!({ "sub": "user:1", "exp": usize::MAX }))
+            .unwrap();
+
+        assert!(issuers.issuer_for(&local).is_none());
+        assert!(TrustedIssuers::default().issuer_for(&sso_token("sso-1", "rust_server")).is_none());
+    }
+
+    fn external_claims(email_verified: Option<bool>) -> ExternalClaims {
+        ExternalClaims {
+            iss: "https://sso.example.com".to_string(),
+            sub: "00u1abc".to_string(),
+            exp: Utc::now().timestamp() as usize + 300,
+            iat: None,
+            jti: Some("abc".to_string()),
+            email: Some("jane@example.com".to_string()),
+            email_verified,
+            name: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_links_existing_user_by_email() {
+        let now = Utc::now().naive_utc();
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([Vec::<user_identity::Model>::new()])
+            .append_query_results([vec![test_user(7)]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 1,
+                rows_affected: 1,
+            }])
+            .append_query_results([vec![user_identity::Model {
+                id: 1,
+                user_id: 7,
+                issuer: "https://sso.example.com".to_string(),
+                subject: "00u1abc".to_string(),
+                created_at: now,
+            }]])
+            .into_connection();
+
+        let external = external_claims(Some(true));
+
+        let user = resolve_user(&db, &test_config(PathBuf::new()), &external)
+            .await
+            .unwrap()
+            .unwrap();
+        let claims = local_claims(&user, &external);
+
+        assert_eq!(claims.id, 7);
+        assert_eq!(claims.ver, 2);
+        assert_eq!(claims.jti, local_claims(&user, &external).jti);
+        assert_eq!(claims.jti.len(), 36);
+        assert!(claims.is_user());
+    }
+
+    #[actix_rt::test]
+    async fn test_unvouched_email_is_not_matched() {
+        for email_verified in [None, Some(false)] {
+            let db = MockDatabase::new(DatabaseBackend::MySql)
+                .append_query_results([Vec::<user_identity::Model>::new()])
+                .into_connection();
+
+            let user = resolve_user(&db, &test_config(PathBuf::new()), &external_claims(email_verified))
+                .await
+                .unwrap();
+
+            assert!(user.is_none());
+            // nothing is looked up by email, let alone linked
+            assert_eq!(db.into_transaction_log().len(), 1);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_unverified_local_account_is_not_linked() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([Vec::<user_identity::Model>::new()])
+            .append_query_results([vec![user_info::Model {
+                email_verified: false,
+                ..test_user(7)
+            }]])
+            .into_connection();
+
+        let user = resolve_user(&db, &test_config(PathBuf::new()), &external_claims(Some(true)))
+            .await
+            .unwrap();
+
+        assert!(user.is_none());
+        assert_eq!(db.into_transaction_log().len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_only_accounts_that_never_had_a_password_are_sso_only() {
+        let identity = user_identity::Model {
+            id: 1,
+            user_id: 7,
+            issuer: "https://sso.example.com".to_string(),
+            subject: "00u1abc".to_string(),
+            created_at: Utc::now().naive_utc(),
+        };
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![identity], vec![]])
+            .into_connection();
+        let sso_user = user_info::Model {
+            password: String::new(),
+            ..test_user(7)
+        };
+
+        assert!(is_sso_only(&db, &sso_user).await.unwrap());
+        assert!(!is_sso_only(&db, &sso_user).await.unwrap());
+
+        // a forced reset or a real password decide without asking the database
+        for stored in [password::RESET_REQUIRED.to_string(), "$argon2id$...".to_string()] {
+            let user = user_info::Model {
+                password: stored,
+                ..test_user(7)
+            };
+            assert!(!is_sso_only(&db, &user).await.unwrap());
+        }
+        assert_eq!(db.into_transaction_log().len(), 2);
+    }
+}
@@ -0,0 +1,286 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use reqwest::Url;
+use validator::{Validate, ValidateEmail, ValidationError, ValidationErrors};
+
+use super::eth;
+use crate::error::ServiceError;
+
+const MAX_EMAIL_LEN: usize = 254;
+const MAX_NAME_LEN: usize = 100;
+const MAX_AGE: i32 = 150;
+const MAX_IMAGE_LEN: usize = 2048;
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 1024;
+
+/// Field name to message, for every field that failed.
+pub type FieldErrors = BTreeMap<String, String>;
+
+fn invalid(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError::new(code).with_message(Cow::from(message))
+}
+
+/// One message per field; the first rule that failed wins.
+pub fn field_errors(errors: &ValidationErrors) -> FieldErrors {
+    errors
+        .field_errors()
+        .into_iter()
+        .filter_map(|(field, errors)| {
+            let error = errors.first()?;
+            let message = match &error.message {
+                Some(message) => message.to_string(),
+                None => format!("is invalid ({})", error.code),
+            };
+
+            Some((field.to_string(), message))
+        })
+        .collect()
+}
+
+/// Runs the `#[validate]` rules of a request model and reports every failed
+/// field in one 422.
+pub fn validate<T: Validate>(model: &T) -> Result<(), ServiceError> {
+    validate_with(model, FieldErrors::new())
+}
+
+/// Like `validate`, adding errors found before the model existed, e.g. while
+/// parsing form fields. Those win per field.
+pub fn validate_with<T: Validate>(model: &T, mut errors: FieldErrors) -> Result<(), ServiceError> {
+    if let Err(invalid) = model.validate() {
+        for (field, message) in field_errors(&invalid) {
+            errors.entry(field).or_insert(message);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(failed(errors));
+    }
+
+    Ok(())
+}
+
+/// The 422 for fields that only turn out invalid later, e.g. against the database.
+pub fn failed(errors: FieldErrors) -> ServiceError {
+    ServiceError::ValidationFailed {
+        error_message: "The request has invalid fields".to_string(),
+        errors,
+    }
+}
+
+/// Parses a text form field, recording a field error instead of failing so
+/// the other fields still get checked.
+pub fn parse_field<T: FromStr + Default>(errors: &mut FieldErrors, field: &str, value: &str) -> T {
+    value.trim().parse().unwrap_or_else(|_| {
+        errors.insert(field.to_owned(), "must be an integer".to_owned());
+        T::default()
+    })
+}
+
+/// Names are trimmed before they are stored, so whitespace alone is empty.
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(invalid("not_blank", "must not be blank"));
+    }
+
+    Ok(())
+}
+
+/// A display name: not blank and at most 100 characters.
+pub fn name(value: &str) -> Result<(), ValidationError> {
+    not_blank(value)?;
+
+    if value.chars().count() > MAX_NAME_LEN {
+        return Err(invalid("name", "must be between 1 and 100 characters"));
+    }
+
+    Ok(())
+}
+
+pub fn age(value: i32) -> Result<(), ValidationError> {
+    if !(0..=MAX_AGE).contains(&value) {
+        return Err(invalid("age", "must be between 0 and 150"));
+    }
+
+    Ok(())
+}
+
+/// A new password. The upper bound keeps hashing cheap for absurd inputs.
+pub fn password(value: &str) -> Result<(), ValidationError> {
+    if !(MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&value.chars().count()) {
+        return Err(invalid("password", "must be between 8 and 1024 characters"));
+    }
+
+    Ok(())
+}
+
+/// An HTML5 email address whose domain has at least one dot.
+pub fn email(value: &str) -> Result<(), ValidationError> {
+    let has_dotted_domain = value
+        .rsplit_once('@')
+        .is_some_and(|(_, domain)| domain.contains('.') && !domain.ends_with('.'));
+
+    if value.len() > MAX_EMAIL_LEN || !value.validate_email() || !has_dotted_domain {
+        return Err(invalid("email", "must be a valid email address"));
+    }
+
+    Ok(())
+}
+
+/// A `0x` prefixed 32 byte hash, like block and transaction hashes.
+pub fn hex_hash(value: &str) -> Result<(), ValidationError> {
+    let valid = value
+        .strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+
+    if !valid {
+        return Err(invalid("hex_hash", "must be 0x followed by 64 hex digits"));
+    }
+
+    Ok(())
+}
+
+/// A 20 byte `0x` address. Mixed case means EIP-55, so the checksum must match.
+pub fn chain_address(value: &str) -> Result<(), ValidationError> {
+    if eth::normalize_address(value).is_none() || !value.starts_with("0x") {
+        return Err(invalid("chain_address", "must be 0x followed by 40 hex digits"));
+    }
+
+    let hex = &value[2..];
+    let mixed_case =
+        hex.chars().any(|c| c.is_ascii_lowercase()) && hex.chars().any(|c| c.is_ascii_uppercase());
+    if mixed_case && eth::to_checksum_address(value).as_deref() != Some(value) {
+        return Err(invalid("chain_address", "has an invalid EIP-55 checksum"));
+    }
+
+    Ok(())
+}
+
+/// `chain_address` for fields where an empty string means "none".
+pub fn optional_chain_address(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    chain_address(value)
+}
+
+/// A profile image: empty, an uploaded avatar or an http(s) URL.
+pub fn image(value: &str) -> Result<(), ValidationError> {
+    if value.len() > MAX_IMAGE_LEN {
+        return Err(invalid("image", "must be at most 2048 characters"));
+    }
+
+    if value.is_empty() || value.starts_with("/avatars/") {
+        return Ok(());
+    }
+
+    let valid =
+        Url::parse(value).is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some());
+    if !valid {
+        return Err(invalid("image", "must be an http(s) URL or an uploaded avatar"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, ResponseError};
+
+    #[derive(Validate)]
+    struct Signup {
+        #[validate(custom(function = "email"))]
+        email: String,
+        #[validate(custom(function = "age"))]
+        age: i32,
+        #[validate(custom(function = "name"))]
+        name: String,
+    }
+
+    #[test]
+    fn test_email() {
+        assert!(email("ada@example.com").is_ok());
+        assert!(email("ada+tag@mail.example.co.uk").is_ok());
+        assert!(email("ada@localhost").is_err());
+        assert!(email("ada@example.").is_err());
+        assert!(email("ada.example.com").is_err());
+        assert!(email("ada @example.com").is_err());
+        assert!(email(&format!("{}@example.com", "a".repeat(250))).is_err());
+    }
+
+    #[test]
+    fn test_password() {
+        assert!(password("correct horse").is_ok());
+        assert!(password(&"x".repeat(1024)).is_ok());
+        assert!(password("").is_err());
+        assert!(password("1234567").is_err());
+        assert!(password(&"x".repeat(1025)).is_err());
+    }
+
+    #[test]
+    fn test_hex_hash() {
+        assert!(hex_hash(&format!("0x{}", "ab".repeat(32))).is_ok());
+        assert!(hex_hash(&format!("0x{}", "AB".repeat(32))).is_ok());
+        assert!(hex_hash(&"ab".repeat(32)).is_err());
+        assert!(hex_hash(&format!("0x{}", "ab".repeat(31))).is_err());
+        assert!(hex_hash(&format!("0x{}", "zz".repeat(32))).is_err());
+    }
+
+    #[test]
+    fn test_chain_address() {
+        assert!(chain_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_ok());
+        assert!(chain_address("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED").is_ok());
+        assert!(chain_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_ok());
+        assert!(chain_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD").is_err());
+        assert!(chain_address("0X5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_err());
+        assert!(chain_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea").is_err());
+        assert!(optional_chain_address("").is_ok());
+        assert!(chain_address("").is_err());
+    }
+
+    #[test]
+    fn test_every_failed_field_is_reported() {
+        let signup = Signup {
+            email: "nope".to_string(),
+            age: 200,
+            name: "  ".to_string(),
+        };
+
+        let err = validate(&signup).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let ServiceError::ValidationFailed { errors, .. } = err else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(
+            errors,
+            FieldErrors::from([
+                ("age".to_string(), "must be between 0 and 150".to_string()),
+                ("email".to_string(), "must be a valid email address".to_string()),
+                ("name".to_string(), "must not be blank".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_win_over_rules() {
+        let mut errors = FieldErrors::new();
+        let age: i32 = parse_field(&mut errors, "age", "old");
+        let signup = Signup {
+            email: "ada@example.com".to_string(),
+            age,
+            name: "Ada".to_string(),
+        };
+
+        assert_eq!(parse_field::<i32>(&mut FieldErrors::new(), "age", " 36 "), 36);
+
+        let Err(ServiceError::ValidationFailed { errors, .. }) = validate_with(&signup, errors) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(errors, FieldErrors::from([("age".to_string(), "must be an integer".to_string())]));
+        assert!(validate(&Signup { age: 36, ..signup }).is_ok());
+    }
+}
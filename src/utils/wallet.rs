@@ -0,0 +1,157 @@
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::sea_query::{Expr, Func};
+use sea_orm::{ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder};
+
+use entities::{tx_info, user_wallet};
+
+use super::constants;
+use super::siwe::SiweMessage;
+
+/// Statement of a wallet link challenge. It names the account, so a signature
+/// collected for one user cannot be replayed to link the wallet to another.
+pub fn link_statement(user_id: i32) -> String {
+    format!("Link this wallet to rust_server account #{}", user_id)
+}
+
+/// EIP-4361 message the wallet owner signs to prove control of `address`.
+pub fn link_challenge(
+    user_id: i32,
+    address: &str,
+    chain_id: u64,
+    nonce: String,
+    expires_at: NaiveDateTime,
+) -> SiweMessage {
+    SiweMessage {
+        domain: constants::SIWE_DOMAIN.clone(),
+        address: address.to_owned(),
+        statement: Some(link_statement(user_id)),
+        uri: constants::APP_BASE_URL.clone(),
+        version: "1".to_owned(),
+        chain_id,
+        nonce,
+        issued_at: Utc::now(),
+        expiration_time: Some(expires_at.and_utc()),
+        not_before: None,
+        request_id: None,
+        resources: Vec::new(),
+    }
+}
+
+pub async fn user_wallets(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<user_wallet::Model>, DbErr> {
+    user_wallet::Entity::find()
+        .filter(user_wallet::Column::UserId.eq(user_id))
+        .order_by_asc(user_wallet::Column::CreatedAt)
+        .all(db)
+        .await
+}
+
+/// Distinct accounts with a verified link to the lowercased `address`, on any
+/// chain. The legacy `user_info.wallet_address` is never proof of ownership.
+pub async fn linked_user_ids(db: &DatabaseConnection, address: &str) -> Result<Vec<i32>, DbErr> {
+    let mut user_ids = user_wallet::Entity::find()
+        .filter(user_wallet::Column::Address.eq(address))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|wallet| wallet.user_id)
+        .collect::<Vec<i32>>();
+
+    user_ids.sort();
+    user_ids.dedup();
+
+    Ok(user_ids)
+}
+
+/// Distinct lowercased addresses of the user's verified wallets, over all chains.
+pub async fn verified_addresses(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<String>, DbErr> {
+    let mut addresses = user_wallets(db, user_id)
+        .await?
+        .into_iter()
+        .map(|wallet| wallet.address)
+        .collect::<Vec<String>>();
+
+    addresses.sort();
+    addresses.dedup();
+
+    Ok(addresses)
+}
+
+/// Transactions from or to any of the user's verified wallets.
+pub async fn user_transactions(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<tx_info::Model>, DbErr> {
+    let addresses = verified_addresses(db, user_id).await?;
+
+    if addresses.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    tx_info::Entity::find()
+        .filter(
+            Condition::any()
+                .add(Expr::expr(Func::lower(Expr::col(tx_info::Column::FromAddress))).is_in(addresses.clone()))
+                .add(Expr::expr(Func::lower(Expr::col(tx_info::Column::ToAddress))).is_in(addresses)),
+        )
+        .all(db)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use sea_orm::{DatabaseBackend, MockDatabase};
+
+    fn linked(id: i32, user_id: i32, chain_id: i64) -> user_wallet::Model {
+        let now = Utc::now().naive_utc();
+
+        user_wallet::Model {
+            id,
+            user_id,
+            chain_id,
+            address: "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23".to_string(),
+            label: String::new(),
+            verified_at: now,
+            created_at: now,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_linked_user_ids_are_distinct() {
+        let db = MockDatabase::new(DatabaseBackend::MySql)
+            .append_query_results([vec![linked(1, 7, 1), linked(2, 7, 137), linked(3, 9, 10)]])
+            .into_connection();
+
+        let user_ids = linked_user_ids(&db, "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23")
+            .await
+            .unwrap();
+
+        assert_eq!(user_ids, vec![7, 9]);
+    }
+
+    #[test]
+    fn test_link_challenge_round_trips() {
+        let expires_at = Utc::now().naive_utc() + Duration::minutes(10);
+        let challenge = link_challenge(
+            7,
+            "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23",
+            1,
+            "32891756abcdef01".to_string(),
+            expires_at,
+        );
+
+        let parsed = challenge.to_string().parse::<SiweMessage>().unwrap();
+
+        assert_eq!(parsed.address, "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23");
+        assert_eq!(parsed.statement, Some(link_statement(7)));
+        assert_ne!(parsed.statement, Some(link_statement(8)));
+        assert_eq!(parsed.nonce, "32891756abcdef01");
+    }
+}